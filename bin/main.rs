@@ -1,6 +1,6 @@
 use std::{path::{PathBuf, Path}, collections::HashMap, io::{BufReader, BufRead}, fs::File, iter::{repeat}};
 
-use cardboard::{data::{globals, project::Project, card::Card}, renderer::{SkiaRenderer, Renderer}, config::sheets::SheetType};
+use cardboard::{data::{globals, project::Project, card::Card}, renderer::{LayerFilter, SkiaRenderer, Renderer}, config::sheets::SheetType};
 use clap::{Parser, Subcommand};
 use lazy_static::lazy_static;
 use log::LevelFilter;
@@ -27,6 +27,16 @@ struct Cardboard {
     #[arg(long, short)]
     verbose: bool,
 
+    /// Only draw elements in the specified layer. May be given more than
+    /// once. Elements without a layer aren't drawn when this is set.
+    #[arg(long = "layer", value_name = "LAYER", global = true)]
+    layers: Vec<String>,
+
+    /// Don't draw elements in the specified layer. May be given more than
+    /// once.
+    #[arg(long = "skip-layer", value_name = "LAYER", global = true)]
+    skipped_layers: Vec<String>,
+
     #[command(subcommand)]
     command: Option<Command>,
 }
@@ -98,7 +108,7 @@ fn main() -> miette::Result<()> {
             .unwrap_or(std::env::current_dir().into_diagnostic()?)
             .canonicalize().into_diagnostic()?;
     let project = Project::load_from_directory(&project_dir)?;
-    let mut renderer = SkiaRenderer::new();
+    let mut renderer = SkiaRenderer::with_layers(LayerFilter { only: cb.layers, skip: cb.skipped_layers });
 
     match cb.command.unwrap_or_default() {
         Command::Sheet { sheet_type, card_list, output_path } => {
@@ -54,9 +54,9 @@ fn main() -> miette::Result<()> {
 
     println!("Setting up test card...");
     let mut test_card = Card::new("test_card_id".to_string(), "version".to_string());
-    test_card.fields_mut().insert("name".to_string(), NAME.to_string());
-    test_card.fields_mut().insert("version".to_string(), VERSION.to_string());
-    test_card.fields_mut().insert("layout".to_string(), "test_layout".to_string());
+    test_card.fields_mut().insert("name".to_string(), NAME.into());
+    test_card.fields_mut().insert("version".to_string(), VERSION.into());
+    test_card.fields_mut().insert("layout".to_string(), "test_layout".into());
     test_project.add_card(test_card);
 
     println!("Initializing Skia rendering engine...");
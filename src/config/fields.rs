@@ -0,0 +1,66 @@
+use std::str::FromStr;
+
+use miette::miette;
+
+use crate::data::field::FieldValue;
+
+// Overrides the inferred type of a card field, e.g.
+//
+//     field "keywords" type="list" delimiter=";"
+//     field "collector_number" type="text"
+#[derive(knuffel::Decode, Clone)]
+pub struct FieldDefinition {
+    #[knuffel(argument)]
+    pub name: String,
+    #[knuffel(property(name="type"), str)]
+    pub field_type: FieldType,
+    #[knuffel(property, default=DEFAULT_LIST_DELIMITER.to_string())]
+    pub delimiter: String,
+}
+
+const DEFAULT_LIST_DELIMITER: &str = ",";
+
+impl FieldDefinition {
+    /// Convert a card's value for this field to the declared type, or return
+    /// `None` if it can't be represented as that type.
+    pub fn convert(&self, value: &FieldValue) -> Option<FieldValue> {
+        match (&self.field_type, value) {
+            (FieldType::Text, value) => Some(FieldValue::Text(value.to_string())),
+            (FieldType::Number, FieldValue::Number(_)) => Some(value.clone()),
+            (FieldType::Number, FieldValue::Text(text)) if text.trim().is_empty() => Some(value.clone()),
+            (FieldType::Number, FieldValue::Text(text)) => text.trim().parse::<f64>().ok().map(FieldValue::Number),
+            (FieldType::Number, _) => None,
+            (FieldType::Boolean, FieldValue::Boolean(_)) => Some(value.clone()),
+            (FieldType::Boolean, FieldValue::Number(number)) => Some(FieldValue::Boolean(*number != 0.0)),
+            (FieldType::Boolean, FieldValue::Text(text)) => match text.trim().to_ascii_lowercase().as_str() {
+                "true" | "yes" | "y" | "1" => Some(FieldValue::Boolean(true)),
+                "false" | "no" | "n" | "0" | "" => Some(FieldValue::Boolean(false)),
+                _ => None,
+            },
+            (FieldType::Boolean, _) => None,
+            (FieldType::List, value) => Some(value.split(&self.delimiter)),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum FieldType {
+    Text,
+    Number,
+    Boolean,
+    List,
+}
+
+impl FromStr for FieldType {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" | "string" => Ok(FieldType::Text),
+            "number" => Ok(FieldType::Number),
+            "boolean" | "bool" => Ok(FieldType::Boolean),
+            "list" => Ok(FieldType::List),
+            _ => Err(miette!(r#"Invalid field type. Expected one of `"text"`, `"number"`, `"boolean"`, or `"list"`."#)),
+        }
+    }
+}
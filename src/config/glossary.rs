@@ -0,0 +1,22 @@
+#[derive(knuffel::Decode)]
+pub struct Glossary {
+    #[knuffel(property, default=DEFAULT_REMINDER_STYLE.to_string())]
+    pub reminder_style: String,
+    #[knuffel(children(name="term"))]
+    pub terms: Vec<Term>,
+}
+
+#[derive(knuffel::Decode)]
+pub struct Term {
+    #[knuffel(argument)]
+    pub keyword: String,
+    #[knuffel(argument)]
+    pub reminder: String,
+}
+
+const DEFAULT_REMINDER_STYLE: &str = "i";
+
+pub struct GlossaryEntry {
+    pub reminder: String,
+    pub style: String,
+}
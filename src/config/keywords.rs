@@ -0,0 +1,49 @@
+use miette::Diagnostic;
+use regex::Regex;
+use thiserror::Error;
+
+use crate::format::keywords::KeywordRule;
+
+#[derive(knuffel::Decode)]
+pub struct KeywordDefinition {
+    #[knuffel(arguments)]
+    pub words: Vec<String>,
+    #[knuffel(property)]
+    pub pattern: Option<String>,
+    #[knuffel(property)]
+    pub style: String,
+}
+
+impl KeywordDefinition {
+    pub fn compile(&self) -> Result<Vec<KeywordRule>, KeywordDefinitionError> {
+        let mut rules = vec![];
+
+        if !self.words.is_empty() {
+            rules.push(
+                KeywordRule::for_words(&self.words, self.style.clone())
+                    .map_err(|err| KeywordDefinitionError::InvalidPattern(self.style.clone(), err))?
+            );
+        }
+
+        if let Some(ref pattern) = self.pattern {
+            rules.push(KeywordRule::new(
+                Regex::new(pattern).map_err(|err| KeywordDefinitionError::InvalidPattern(self.style.clone(), err))?,
+                self.style.clone(),
+            ));
+        }
+
+        if rules.is_empty() {
+            Err(KeywordDefinitionError::Empty(self.style.clone()))
+        } else {
+            Ok(rules)
+        }
+    }
+}
+
+#[derive(Error, Diagnostic, Debug)]
+pub enum KeywordDefinitionError {
+    #[error("invalid keyword pattern for style \"{0}\": {1}")]
+    InvalidPattern(String, regex::Error),
+    #[error("keyword definition for style \"{0}\" has neither words nor a pattern")]
+    Empty(String),
+}
@@ -1,8 +1,11 @@
 use std::collections::HashMap;
 
-use crate::layout::model::styles::{color::Color, TextStyle as LayoutTextStyle};
+use crate::{layout::model::styles::{color::Color, TextStyle as LayoutTextStyle}, format::keywords::KeywordRule};
 
 pub mod colors;
+pub mod fields;
+pub mod glossary;
+pub mod keywords;
 pub mod sheets;
 pub mod styles;
 pub mod util;
@@ -17,12 +20,24 @@ pub struct RawConfig {
     pub pdf_subject: Option<String>,
     #[knuffel(child, unwrap(argument))]
     pub pdf_keywords: Option<String>,
+    #[knuffel(child, unwrap(argument))]
+    pub strict_markup: Option<bool>,
+    #[knuffel(child, unwrap(argument))]
+    pub list_markup: Option<bool>,
+    #[knuffel(child, unwrap(argument))]
+    pub lang: Option<String>,
     #[knuffel(children(name="color"))]
     colors: Vec<colors::ColorDefinition>,
     #[knuffel(children(name="text-style"))]
     text_styles: Vec<styles::TextStyle>,
     #[knuffel(children(name="sheet-type"))]
     sheet_types: Vec<sheets::SheetType>,
+    #[knuffel(children(name="keyword"))]
+    keywords: Vec<keywords::KeywordDefinition>,
+    #[knuffel(children(name="glossary"))]
+    glossaries: Vec<glossary::Glossary>,
+    #[knuffel(children(name="field"))]
+    fields: Vec<fields::FieldDefinition>,
 }
 
 impl RawConfig {
@@ -46,6 +61,38 @@ impl RawConfig {
         style_map
     }
 
+    pub fn get_keyword_rules(&self) -> miette::Result<Vec<KeywordRule>> {
+        let mut rules = vec![];
+
+        for keyword in &self.keywords {
+            rules.extend(keyword.compile()?);
+        }
+
+        Ok(rules)
+    }
+
+    pub fn get_glossary(&self) -> HashMap<String, glossary::GlossaryEntry> {
+        let mut glossary_map = HashMap::new();
+
+        for glossary in &self.glossaries {
+            for term in &glossary.terms {
+                glossary_map.insert(
+                    term.keyword.to_lowercase(),
+                    glossary::GlossaryEntry {
+                        reminder: term.reminder.clone(),
+                        style: glossary.reminder_style.clone(),
+                    },
+                );
+            }
+        }
+
+        glossary_map
+    }
+
+    pub fn get_field_definitions(&self) -> HashMap<String, fields::FieldDefinition> {
+        self.fields.iter().map(|field| (field.name.clone(), field.clone())).collect()
+    }
+
     pub fn get_sheet_layouts(&self) -> miette::Result<HashMap<String, sheets::layout::Sheet>> {
         let mut sheet_map = HashMap::new();
 
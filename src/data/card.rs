@@ -2,12 +2,14 @@ use std::{collections::HashMap, sync::OnceLock};
 
 use handlebars::Context;
 use miette::IntoDiagnostic;
-use serde::Serialize;
+use serde::{Serialize, ser::SerializeMap};
+
+use super::field::FieldValue;
 
 pub struct Card {
     pub id: String,
     pub set: String,
-    fields: HashMap<String, String>,
+    fields: HashMap<String, FieldValue>,
     handlebars_context: OnceLock<Context>,
 }
 
@@ -16,17 +18,22 @@ impl Card {
         Card { id, set, fields: HashMap::new(), handlebars_context: OnceLock::new() }
     }
 
-    pub fn fields_mut(&mut self) -> &mut HashMap<String, String> {
+    pub fn fields_mut(&mut self) -> &mut HashMap<String, FieldValue> {
         &mut self.fields
     }
 
     pub fn layout_name(&self) -> Option<String> {
-        match self.fields.get("layout").map(|layout_name| layout_name.clone()) {
+        match self.fields.get("layout").map(|layout_name| layout_name.to_string()) {
             // If there is a layout name, but it's blank, treat that as if there were no layout name
             Some(l) if l == "" => None,
             layout => layout,
         }
     }
+
+    pub fn lang(&self) -> Option<&str> {
+        // As with layouts, a blank language is treated as no language at all
+        self.fields.get("lang").and_then(FieldValue::as_str).map(|lang| lang.trim()).filter(|lang| !lang.is_empty())
+    }
 }
 
 impl<'a> TryFrom<&'a Card> for &'a Context {
@@ -63,7 +70,12 @@ impl<'a> Serialize for Card {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer {
-        serializer.collect_map(std::iter::once((&"id".to_string(), &self.id.clone())).chain(self.fields.iter()))
+        let mut map = serializer.serialize_map(Some(self.fields.len() + 1))?;
+        map.serialize_entry("id", &self.id)?;
+        for (name, value) in &self.fields {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
     }
 }
 
@@ -74,7 +86,7 @@ pub mod loaders {
     use miette::{IntoDiagnostic, Diagnostic};
     use thiserror::Error;
 
-    use super::Card;
+    use super::{Card, FieldValue};
 
     pub fn load_csv<P: AsRef<Path>>(csv_path: P) -> miette::Result<Vec<Card>> {
         let file_name_stem = csv_path.as_ref().file_stem().and_then(|p| p.to_str()).map(|p| p.to_string());
@@ -87,7 +99,8 @@ pub mod loaders {
         
         Ok(csv_reader
             .deserialize()
-            .map(|hash_result| hash_result.unwrap_or_default())
+            .map(|hash_result: Result<HashMap<String, String>, _>| hash_result.unwrap_or_default())
+            .map(|card_hash| card_hash.into_iter().map(|(name, raw)| (name, FieldValue::infer(&raw))).collect())
             .enumerate()
             .map(build_card_from_document_named(file_name_stem))
             .collect()
@@ -100,18 +113,21 @@ pub mod loaders {
         let first_sheet = workbook.worksheet_range_at(0).map_or(Err(CardLoadingError::EmptyWorkbook(format!("{}", excel_path.as_ref().display()))).into_diagnostic(), |result| result.into_diagnostic())?;
         let rows = calamine::RangeDeserializerBuilder::new().from_range(&first_sheet).into_diagnostic()?;
 
+        // Cells keep the types they have in the spreadsheet, so numbers stay
+        // numbers and text stays text
         Ok(rows
-            .map(|hash_result| hash_result.unwrap_or_default())
+            .map(|hash_result: Result<HashMap<String, calamine::DataType>, _>| hash_result.unwrap_or_default())
+            .map(|card_hash| card_hash.into_iter().map(|(name, cell)| (name, FieldValue::from(cell))).collect())
             .enumerate()
             .map(build_card_from_document_named(file_name_stem))
             .collect()
         )
     }
 
-    fn build_card_from_document_named(doc_name: Option<String>) -> Box<dyn Fn((usize, HashMap<String, String>)) -> Card> {
+    fn build_card_from_document_named(doc_name: Option<String>) -> Box<dyn Fn((usize, HashMap<String, FieldValue>)) -> Card> {
         Box::new(move |(idx, mut card_hash)| {
             let mut card = Card::new(
-                match card_hash.remove("id") {
+                match card_hash.remove("id").map(|card_id| card_id.to_string()) {
                     None => generate_id(idx, &doc_name),
                     // Treat a blank id the same as an absent id
                     Some(blank_id) if blank_id == "" => generate_id(idx, &doc_name),
@@ -0,0 +1,174 @@
+use std::fmt::Display;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Serialize, ser::SerializeSeq};
+
+lazy_static! {
+    // Only numbers written the way a number would normally be written count.
+    // Anything else (leading zeros, exponents, a leading "+") is more likely
+    // to be an identifier or a bit of rules text that happens to look numeric.
+    static ref CANONICAL_NUMBER: Regex = Regex::new(r"\A-?(?:0|[1-9][0-9]*)(?:\.[0-9]+)?\z").unwrap();
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum FieldValue {
+    Text(String),
+    Number(f64),
+    Boolean(bool),
+    List(Vec<FieldValue>),
+}
+
+impl FieldValue {
+    /// Guess the type of a value that came out of an untyped data source,
+    /// like a CSV file.
+    ///
+    /// A value is only treated as a number or a boolean when it would be
+    /// printed back out exactly as it was written, so "2.50" and "TRUE" stay
+    /// text. Fields that need to be read more loosely can declare a type.
+    pub fn infer(raw: &str) -> FieldValue {
+        if CANONICAL_NUMBER.is_match(raw) {
+            if let Ok(number) = raw.parse::<f64>() {
+                let value = FieldValue::Number(number);
+                if value.to_string() == raw {
+                    return value;
+                }
+            }
+        }
+
+        match raw {
+            "true" => FieldValue::Boolean(true),
+            "false" => FieldValue::Boolean(false),
+            _ => FieldValue::Text(raw.to_string()),
+        }
+    }
+
+    /// Split a textual value into a list on `delimiter`, inferring the type
+    /// of each item.
+    pub fn split(&self, delimiter: &str) -> FieldValue {
+        match self {
+            FieldValue::List(_) => self.clone(),
+            FieldValue::Text(text) => FieldValue::List(
+                text.split(delimiter)
+                    .map(|item| item.trim())
+                    .filter(|item| !item.is_empty())
+                    .map(FieldValue::infer)
+                    .collect()
+            ),
+            other => FieldValue::List(vec![other.clone()]),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            FieldValue::Text(text) => Some(text.as_str()),
+            _ => None,
+        }
+    }
+
+    fn as_integer(number: f64) -> Option<i64> {
+        if number.fract() == 0.0 && number.abs() < (i64::MAX as f64) {
+            Some(number as i64)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for FieldValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldValue::Text(text) => write!(f, "{}", text),
+            FieldValue::Number(number) => match FieldValue::as_integer(*number) {
+                Some(integer) => write!(f, "{}", integer),
+                None => write!(f, "{}", number),
+            },
+            FieldValue::Boolean(boolean) => write!(f, "{}", boolean),
+            FieldValue::List(items) => {
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(text: String) -> Self {
+        FieldValue::Text(text)
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(text: &str) -> Self {
+        FieldValue::Text(text.to_string())
+    }
+}
+
+impl From<calamine::DataType> for FieldValue {
+    fn from(cell: calamine::DataType) -> Self {
+        match cell {
+            calamine::DataType::Int(integer) => FieldValue::Number(integer as f64),
+            calamine::DataType::Float(number) => FieldValue::Number(number),
+            calamine::DataType::Bool(boolean) => FieldValue::Boolean(boolean),
+            calamine::DataType::String(text) => FieldValue::Text(text),
+            calamine::DataType::Empty => FieldValue::Text(String::new()),
+            other => FieldValue::Text(other.to_string()),
+        }
+    }
+}
+
+impl Serialize for FieldValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer {
+        match self {
+            FieldValue::Text(text) => serializer.serialize_str(text),
+            // Whole numbers are serialized as integers so that templates
+            // render them as "3" rather than "3.0"
+            FieldValue::Number(number) => match FieldValue::as_integer(*number) {
+                Some(integer) => serializer.serialize_i64(integer),
+                None => serializer.serialize_f64(*number),
+            },
+            FieldValue::Boolean(boolean) => serializer.serialize_bool(*boolean),
+            FieldValue::List(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_infers_field_types() -> () {
+        assert_eq!(FieldValue::infer("3"), FieldValue::Number(3.0));
+        assert_eq!(FieldValue::infer("-2.5"), FieldValue::Number(-2.5));
+        assert_eq!(FieldValue::infer("0"), FieldValue::Number(0.0));
+        assert_eq!(FieldValue::infer("007"), FieldValue::Text("007".to_string()));
+        assert_eq!(FieldValue::infer("1e3"), FieldValue::Text("1e3".to_string()));
+        assert_eq!(FieldValue::infer("+1"), FieldValue::Text("+1".to_string()));
+        assert_eq!(FieldValue::infer("1.10"), FieldValue::Text("1.10".to_string()));
+        assert_eq!(FieldValue::infer("-0"), FieldValue::Text("-0".to_string()));
+        assert_eq!(FieldValue::infer("true"), FieldValue::Boolean(true));
+        assert_eq!(FieldValue::infer("TRUE"), FieldValue::Text("TRUE".to_string()));
+        assert_eq!(FieldValue::infer("false"), FieldValue::Boolean(false));
+        assert_eq!(FieldValue::infer(""), FieldValue::Text(String::new()));
+        assert_eq!(
+            FieldValue::infer("Flying; Reach ;;2").split(";"),
+            FieldValue::List(vec![FieldValue::Text("Flying".to_string()), FieldValue::Text("Reach".to_string()), FieldValue::Number(2.0)]),
+        );
+        assert_eq!(FieldValue::Number(4.0).to_string(), "4");
+        assert_eq!(FieldValue::Number(4.25).to_string(), "4.25");
+    }
+}
@@ -1,3 +1,4 @@
 pub mod card;
+pub mod field;
 pub mod globals;
 pub mod project;
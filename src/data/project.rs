@@ -1,9 +1,10 @@
 use std::{collections::HashMap, path::Path, fs};
 
+use handlebars::Handlebars;
 use miette::{Diagnostic, IntoDiagnostic};
 use thiserror::Error;
 
-use crate::{layout::model::{Layout, styles::{color::Color, TextStyle}}, config::{sheets::layout::Sheet, RawConfig}};
+use crate::{layout::{model::{Layout, LayoutDefinition, ComponentLibrary, elements::component::Component, styles::{color::Color, TextStyle}}, templates::{self, TemplateContext}}, config::{sheets::layout::Sheet, RawConfig, glossary::GlossaryEntry, fields::FieldDefinition}, format::{keywords::KeywordRule, glossary::ReminderText}};
 
 use super::{globals, card::{Card, self}};
 
@@ -11,10 +12,20 @@ pub struct Project {
     base_dir: String,
     cards: HashMap<String, Card>,
     layouts: HashMap<String, Layout>,
+    layout_definitions: HashMap<String, LayoutDefinition>,
+    components: HashMap<String, Component>,
     colors: HashMap<String, Color>,
     sheet_layouts: HashMap<String, Sheet>,
     images: HashMap<String, String>,
     text_styles: HashMap<String, Vec<TextStyle>>,
+    keyword_rules: Vec<KeywordRule>,
+    glossary: HashMap<String, GlossaryEntry>,
+    templates: Handlebars<'static>,
+    field_definitions: HashMap<String, FieldDefinition>,
+    pub strict_markup: bool,
+    // Whether lines of text starting with "- " or "1. " become list items
+    pub list_markup: bool,
+    pub lang: Option<String>,
     pub pdf_metadata: PdfMetadata,
 }
 
@@ -24,10 +35,19 @@ impl Project {
             base_dir: String::new(),
             cards: HashMap::new(),
             layouts: HashMap::new(),
+            layout_definitions: HashMap::new(),
+            components: HashMap::new(),
             colors: HashMap::new(),
             sheet_layouts: HashMap::new(),
             images: HashMap::new(),
             text_styles: HashMap::new(),
+            keyword_rules: vec![],
+            glossary: HashMap::new(),
+            templates: templates::new_registry(),
+            field_definitions: HashMap::new(),
+            strict_markup: false,
+            list_markup: false,
+            lang: None,
             pdf_metadata: PdfMetadata::default(),
         }
     }
@@ -38,6 +58,12 @@ impl Project {
             let mut project = Project::new();
             project.base_dir = format!("{}", project_dir.as_ref().display());
             project.scan_dir(project_dir)?;
+            // Layouts can extend layouts from other files, so they can only
+            // be resolved once every layout file has been read
+            project.resolve_layouts()?;
+            // Field definitions can come from any config file, so they can
+            // only be applied once every card has been loaded
+            project.apply_field_definitions();
 
             log::info!("Finished loading project");
             Ok(project)
@@ -104,10 +130,23 @@ impl Project {
                     "layout" => {
                         let file_contents_bytes = fs::read(&path).into_diagnostic()?;
                         let file_contents_str = std::str::from_utf8(file_contents_bytes.as_slice()).into_diagnostic()?;
-                        let layout: Layout = knuffel::parse(relative_path, file_contents_str)?;
-                        self.register_layout(stem, layout);
+                        let mut definition: LayoutDefinition = knuffel::parse(relative_path, file_contents_str)?;
+                        for component in std::mem::take(&mut definition.components) {
+                            log::info!("Successfully loaded component \"{}\" from file {}", component.name, relative_path);
+                            self.register_component(component);
+                        }
+                        self.layout_definitions.insert(stem.to_string(), definition);
                         log::info!("Successfully loaded layout \"{}\" from file {}", stem, relative_path);
                     },
+                    "component" => {
+                        let file_contents_bytes = fs::read(&path).into_diagnostic()?;
+                        let file_contents_str = std::str::from_utf8(file_contents_bytes.as_slice()).into_diagnostic()?;
+                        let library: ComponentLibrary = knuffel::parse(relative_path, file_contents_str)?;
+                        for component in library.components {
+                            log::info!("Successfully loaded component \"{}\" from file {}", component.name, relative_path);
+                            self.register_component(component);
+                        }
+                    },
                     "csv" => {
                         log::info!("Found card set \"{}\" in file {}",
                             stem,
@@ -143,23 +182,70 @@ impl Project {
                         let new_text_style_count = new_text_styles.len();
                         self.text_styles.extend(new_text_styles);
 
+                        let new_keyword_rules = config.get_keyword_rules()?;
+                        let new_keyword_rule_count = new_keyword_rules.len();
+                        self.keyword_rules.extend(new_keyword_rules);
+
+                        let new_glossary = config.get_glossary();
+                        let new_glossary_count = new_glossary.len();
+                        self.glossary.extend(new_glossary);
+
+                        self.field_definitions.extend(config.get_field_definitions());
+
                         let new_sheet_layouts = config.get_sheet_layouts()?;
                         let new_sheet_layout_count = new_sheet_layouts.len();
                         self.sheet_layouts.extend(new_sheet_layouts);
     
+                        if let Some(strict_markup) = config.strict_markup {
+                            self.strict_markup = strict_markup;
+                        }
+
+                        if let Some(list_markup) = config.list_markup {
+                            self.list_markup = list_markup;
+                        }
+
+                        if let Some(lang) = config.lang {
+                            self.lang = Some(lang);
+                        }
+
                         self.pdf_metadata.author = config.pdf_author;
                         self.pdf_metadata.title = config.pdf_title;
                         self.pdf_metadata.subject = config.pdf_subject;
                         self.pdf_metadata.keywords = config.pdf_keywords;
     
                         log::info!(
-                            "Successfully loaded {colors} colors, {styles} text styles, and {layouts} sheet layouts from file {file}",
+                            "Successfully loaded {colors} colors, {styles} text styles, {keywords} keyword rules, {terms} glossary terms, and {layouts} sheet layouts from file {file}",
                             colors=new_color_count,
                             styles=new_text_style_count,
+                            keywords=new_keyword_rule_count,
+                            terms=new_glossary_count,
                             layouts=new_sheet_layout_count,
                             file=relative_path,
                         );
                     },
+                    "hbs" => {
+                        let file_contents_bytes = fs::read(&path).into_diagnostic()?;
+                        let file_contents_str = std::str::from_utf8(file_contents_bytes.as_slice()).into_diagnostic()?;
+                        // Partials are named by their path within the
+                        // project, so `rules/reminder.hbs` is included with
+                        // `{{> rules/reminder}}`
+                        let partial_name = Path::new(relative_path).with_extension("")
+                            .components()
+                            .filter_map(|component| component.as_os_str().to_str())
+                            .collect::<Vec<_>>()
+                            .join("/");
+                        if self.templates.get_template(&partial_name).is_some() {
+                            log::warn!("Partial \"{}\" is declared more than once. Using the last declaration loaded.", partial_name);
+                        }
+                        self.templates.register_partial(&partial_name, file_contents_str).into_diagnostic()?;
+                        log::info!("Successfully loaded partial \"{}\" from file {}", partial_name, relative_path);
+                    },
+                    "rhai" => {
+                        let file_contents_bytes = fs::read(&path).into_diagnostic()?;
+                        let file_contents_str = std::str::from_utf8(file_contents_bytes.as_slice()).into_diagnostic()?;
+                        let helper_count = templates::scripts::register_script(&mut self.templates, relative_path, file_contents_str)?;
+                        log::info!("Successfully loaded {} helpers from script {}", helper_count, relative_path);
+                    },
                     image_ext @ ("bmp" | "png" | "jpg" | "jpeg" | "gif") => {
                         let image_name = relative_path.strip_suffix(image_ext).and_then(|p| p.strip_suffix(".")).unwrap_or(stem);
                         self.images.insert(image_name.to_string(), path.display().to_string());
@@ -175,6 +261,68 @@ impl Project {
         Ok(())
     }
 
+    fn resolve_layouts(&mut self) -> Result<(), ProjectConfigurationError> {
+        let mut definitions = std::mem::take(&mut self.layout_definitions);
+        let mut names: Vec<String> = definitions.keys().cloned().collect();
+        names.sort();
+        for name in names {
+            self.resolve_layout(&name, &mut definitions, &mut vec![])?;
+        }
+        Ok(())
+    }
+
+    // Turn the definition for `name` into a layout, resolving whatever it
+    // extends first. `in_progress` holds the chain of layouts currently being
+    // resolved, so that cycles can be caught.
+    fn resolve_layout(&mut self, name: &str, definitions: &mut HashMap<String, LayoutDefinition>, in_progress: &mut Vec<String>) -> Result<(), ProjectConfigurationError> {
+        let definition = match definitions.remove(name) {
+            Some(definition) => definition,
+            // Either it's already been resolved or it isn't a project layout
+            None => return Ok(()),
+        };
+
+        let layout = match definition.extends.clone() {
+            None => definition.into_layout()
+                .ok_or_else(|| ProjectConfigurationError::LayoutMissingGeometry(name.to_string()))?,
+            // A layout that extends its own name builds on the builtin one
+            Some(parent_name) if parent_name == name => {
+                let parent = globals::layout_named(&parent_name)
+                    .ok_or_else(|| ProjectConfigurationError::UnknownParentLayout(name.to_string(), parent_name.clone()))?;
+                definition.extend(parent)
+            },
+            Some(parent_name) => {
+                in_progress.push(name.to_string());
+                if in_progress.contains(&parent_name) {
+                    in_progress.push(parent_name);
+                    return Err(ProjectConfigurationError::LayoutCycle(in_progress.join(" -> ")));
+                }
+                self.resolve_layout(&parent_name, definitions, in_progress)?;
+                in_progress.pop();
+
+                let parent = self.layout_named(&parent_name)
+                    .ok_or_else(|| ProjectConfigurationError::UnknownParentLayout(name.to_string(), parent_name.clone()))?;
+                definition.extend(parent)
+            },
+        };
+        log::debug!("Resolved layout \"{}\"", name);
+        self.register_layout(name, layout);
+        Ok(())
+    }
+
+    fn apply_field_definitions(&mut self) -> () {
+        for card in self.cards.values_mut() {
+            let card_id = card.id.clone();
+            for (name, value) in card.fields_mut().iter_mut() {
+                if let Some(definition) = self.field_definitions.get(name) {
+                    match definition.convert(value) {
+                        Some(converted) => *value = converted,
+                        None => log::warn!("Field \"{}\" of card {} should be a {:?}, but its value \"{}\" can't be converted. Leaving it as is.", name, card_id, definition.field_type, value),
+                    }
+                }
+            }
+        }
+    }
+
     pub fn card_by_id(&self, id: &str) -> Result<&Card, ProjectConfigurationError> {
         self.cards.get(id)
             .ok_or_else(|| ProjectConfigurationError::NoSuchCard(id.to_string()))
@@ -209,6 +357,20 @@ impl Project {
             .or_else(|| globals::style_named(name))
     }
 
+    pub fn keyword_rules(&self) -> &[KeywordRule] {
+        self.keyword_rules.as_slice()
+    }
+
+    pub fn reminder_text_for(&self, keyword: &str) -> Option<ReminderText<'_>> {
+        self.glossary
+            .get(&keyword.to_lowercase())
+            .map(|entry| ReminderText { text: entry.reminder.as_str(), style: entry.style.as_str() })
+    }
+
+    pub fn template_context_for<'a>(&'a self, card: &'a Card) -> miette::Result<TemplateContext<'a>> {
+        Ok(TemplateContext::new(&self.templates, card.try_into()?))
+    }
+
     pub fn layout_named(&self, name: &str) -> Option<&Layout> {
         self.layouts
             .get(name)
@@ -219,6 +381,17 @@ impl Project {
         self.layouts.insert(name.to_string(), layout);
     }
 
+    pub fn component_named(&self, name: &str) -> Option<&Component> {
+        self.components.get(name)
+    }
+
+    pub fn register_component(&mut self, component: Component) -> () {
+        if self.components.contains_key(&component.name) {
+            log::warn!("Component \"{}\" is declared more than once. Using the last declaration loaded.", component.name);
+        }
+        self.components.insert(component.name.clone(), component);
+    }
+
     pub fn sheet_type_named(&self, name: &str) -> Option<&Sheet> {
         self.sheet_layouts.get(name)
     }
@@ -249,6 +422,12 @@ pub enum ProjectConfigurationError {
     NoLayoutFound(String),
     #[error("couldn't find a definition for a color named '{0}'")]
     InvalidColorName(String),
+    #[error("layout '{0}' extends '{1}', but no layout with that name exists")]
+    UnknownParentLayout(String, String),
+    #[error("layouts extend each other in a cycle: {0}")]
+    LayoutCycle(String),
+    #[error("layout '{0}' has no geometry (add a geometry block, or extend another layout)")]
+    LayoutMissingGeometry(String),
     #[error("project path {0} is not a directory")]
     NotADirectory(String),
     #[error("{0}")]
@@ -0,0 +1,133 @@
+use super::{FormattedTextInstruction, parse};
+
+pub const KEYWORD_TAG: &str = "kw";
+
+pub struct ReminderText<'a> {
+    pub text: &'a str,
+    pub style: &'a str,
+}
+
+// Replace every <kw>...</kw> span with the keyword itself followed by its
+// reminder text, e.g. "Flying (<i>This creature can't be blocked...</i>)".
+// Keywords that `lookup` doesn't know about are left as bare text.
+pub fn expand<'g, F>(instructions: Vec<FormattedTextInstruction>, lookup: F) -> Vec<FormattedTextInstruction>
+where
+    F: Fn(&str) -> Option<ReminderText<'g>>,
+{
+    let mut expanded = Vec::with_capacity(instructions.len());
+    let mut current_keyword: Option<String> = None;
+    // Keyword tags we gave up on, whose closing tags still need to be dropped
+    let mut abandoned_keywords = 0usize;
+
+    for instruction in instructions {
+        match (instruction, current_keyword.as_mut()) {
+            (FormattedTextInstruction::PushStyle(tag), None) if tag == KEYWORD_TAG => {
+                current_keyword = Some(String::new());
+            },
+            (FormattedTextInstruction::AddText(text), Some(keyword)) => {
+                keyword.push_str(&text);
+            },
+            (FormattedTextInstruction::PopStyle(tag), Some(keyword)) if tag == KEYWORD_TAG => {
+                let keyword = keyword.trim().to_string();
+                let reminder = lookup(&keyword);
+                expanded.push(FormattedTextInstruction::AddText(keyword));
+                if let Some(ReminderText { text, style }) = reminder {
+                    expanded.push(FormattedTextInstruction::AddText(" (".to_string()));
+                    expanded.push(FormattedTextInstruction::PushStyle(style.to_string()));
+                    // Reminder text goes inside parentheses, so it can't hold a list
+                    expanded.extend(parse(text, false));
+                    expanded.push(FormattedTextInstruction::PopStyle(style.to_string()));
+                    expanded.push(FormattedTextInstruction::AddText(")".to_string()));
+                }
+                current_keyword = None;
+            },
+            (other, Some(keyword)) => {
+                // Anything other than plain text inside a keyword tag is
+                // markup we can't sensibly expand, so give up on this keyword
+                // and pass everything else through untouched. There's no
+                // style for the keyword tag itself, so it's dropped, along
+                // with its closing tag.
+                expanded.push(FormattedTextInstruction::AddText(std::mem::take(keyword)));
+                abandoned_keywords += 1;
+                current_keyword = None;
+                match other {
+                    FormattedTextInstruction::PushStyle(tag) if tag == KEYWORD_TAG => current_keyword = Some(String::new()),
+                    other => expanded.push(other),
+                }
+            },
+            (FormattedTextInstruction::PopStyle(tag), None) if tag == KEYWORD_TAG && abandoned_keywords > 0 => {
+                abandoned_keywords -= 1;
+            },
+            (other, None) => expanded.push(other),
+        }
+    }
+
+    if let Some(keyword) = current_keyword {
+        // Unclosed keyword tag: leave the text as it was written
+        expanded.push(FormattedTextInstruction::AddText(keyword));
+    }
+
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{FormattedTextInstruction, parse};
+
+    use super::{expand, ReminderText};
+
+    #[test]
+    fn it_expands_keywords_with_reminder_text() -> () {
+        let instructions = parse("<kw>Flying</kw>, <kw>Haste</kw>, <kw>Ward</kw>", false);
+        let expanded = expand(instructions, |keyword| match keyword {
+            "Flying" => Some(ReminderText { text: "Can only be blocked by <b>flying</b> creatures.", style: "i" }),
+            "Haste" => Some(ReminderText { text: "Can attack right away.", style: "reminder" }),
+            _ => None,
+        });
+
+        assert_eq!(
+            expanded,
+            vec![
+                FormattedTextInstruction::AddText("Flying".to_string()),
+                FormattedTextInstruction::AddText(" (".to_string()),
+                FormattedTextInstruction::PushStyle("i".to_string()),
+                FormattedTextInstruction::AddText("Can only be blocked by ".to_string()),
+                FormattedTextInstruction::PushStyle("b".to_string()),
+                FormattedTextInstruction::AddText("flying".to_string()),
+                FormattedTextInstruction::PopStyle("b".to_string()),
+                FormattedTextInstruction::AddText(" creatures.".to_string()),
+                FormattedTextInstruction::PopStyle("i".to_string()),
+                FormattedTextInstruction::AddText(")".to_string()),
+                FormattedTextInstruction::AddText(", ".to_string()),
+                FormattedTextInstruction::AddText("Haste".to_string()),
+                FormattedTextInstruction::AddText(" (".to_string()),
+                FormattedTextInstruction::PushStyle("reminder".to_string()),
+                FormattedTextInstruction::AddText("Can attack right away.".to_string()),
+                FormattedTextInstruction::PopStyle("reminder".to_string()),
+                FormattedTextInstruction::AddText(")".to_string()),
+                FormattedTextInstruction::AddText(", ".to_string()),
+                FormattedTextInstruction::AddText("Ward".to_string()),
+            ]
+        )
+    }
+
+    #[test]
+    fn it_drops_keyword_tags_it_cannot_expand() -> () {
+        let instructions = parse("<i><kw>Fly<b>ing</b></kw> and <kw>Haste</i>", false);
+        let expanded = expand(instructions, |_| None);
+
+        assert_eq!(
+            expanded,
+            vec![
+                FormattedTextInstruction::PushStyle("i".to_string()),
+                FormattedTextInstruction::AddText("Fly".to_string()),
+                FormattedTextInstruction::PushStyle("b".to_string()),
+                FormattedTextInstruction::AddText("ing".to_string()),
+                FormattedTextInstruction::PopStyle("b".to_string()),
+                FormattedTextInstruction::AddText(" and ".to_string()),
+                FormattedTextInstruction::AddText("Haste".to_string()),
+                FormattedTextInstruction::PopStyle("i".to_string()),
+            ]
+        )
+    }
+}
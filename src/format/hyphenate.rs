@@ -0,0 +1,95 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use hyphenation::{Hyphenator, Language, Load, Standard};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+pub const SOFT_HYPHEN: char = '\u{00AD}';
+
+lazy_static! {
+    // Runs of letters, along with any soft hyphens the author already put in
+    static ref WORD: Regex = Regex::new(r"[\p{L}\p{M}\u{00AD}]+").unwrap();
+    // Loading a dictionary means deserializing it, so only do that once per
+    // language. Languages without a dictionary are remembered as `None`.
+    static ref DICTIONARIES: Mutex<HashMap<Language, Option<Arc<Standard>>>> = Mutex::new(HashMap::new());
+}
+
+/// Insert soft hyphens at every hyphenation opportunity in `text`, according
+/// to the bundled dictionary for the language `lang` (a BCP 47 tag). Words
+/// that already contain soft hyphens are left alone, so authors can override
+/// the dictionary on a word-by-word basis.
+///
+/// Returns `None` if there's no dictionary for the language.
+pub fn hyphenate(text: &str, lang: &str) -> Option<String> {
+    let dictionary = dictionary_for(language_for(lang)?)?;
+    let mut hyphenated = String::with_capacity(text.len());
+    let mut position = 0usize;
+
+    for word in WORD.find_iter(text) {
+        hyphenated.push_str(&text[position..word.start()]);
+        if word.as_str().contains(SOFT_HYPHEN) {
+            hyphenated.push_str(word.as_str());
+        } else {
+            let mut word_position = 0usize;
+            for break_index in dictionary.hyphenate(word.as_str()).breaks {
+                hyphenated.push_str(&word.as_str()[word_position..break_index]);
+                hyphenated.push(SOFT_HYPHEN);
+                word_position = break_index;
+            }
+            hyphenated.push_str(&word.as_str()[word_position..]);
+        }
+        position = word.end();
+    }
+    hyphenated.push_str(&text[position..]);
+
+    Some(hyphenated)
+}
+
+fn language_for(lang: &str) -> Option<Language> {
+    let lang = lang.to_ascii_lowercase().replace("_", "-");
+    let primary_language = lang.split('-').next().unwrap_or_default();
+
+    Language::try_from_code(&lang).or_else(|| match primary_language {
+        // Some languages only have dictionaries for specific variants, so
+        // pick a reasonable one when the tag doesn't say
+        "en" => Some(Language::EnglishUS),
+        "de" => Some(Language::German1996),
+        "el" => Some(Language::GreekMono),
+        "mn" => Some(Language::Mongolian),
+        "nb" | "no" => Some(Language::NorwegianBokmal),
+        "sr" => Some(Language::SerbianCyrillic),
+        other => Language::try_from_code(other),
+    })
+}
+
+fn dictionary_for(language: Language) -> Option<Arc<Standard>> {
+    let mut dictionaries = DICTIONARIES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    dictionaries.entry(language).or_insert_with(|| {
+        match Standard::from_embedded(language) {
+            Ok(dictionary) => Some(Arc::new(dictionary)),
+            Err(err) => {
+                log::warn!("Unable to load the hyphenation dictionary for {}: {}", language, err);
+                None
+            },
+        }
+    }).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_inserts_soft_hyphens() -> () {
+        assert_eq!(
+            hyphenate("Hyphenation, naturally!", "en").unwrap(),
+            "Hy\u{ad}phen\u{ad}a\u{ad}tion, nat\u{ad}u\u{ad}rally!",
+        );
+        // Words with soft hyphens already in them are left alone
+        assert_eq!(
+            hyphenate("hyphen\u{ad}ation", "en-US").unwrap(),
+            "hyphen\u{ad}ation",
+        );
+        assert_eq!(hyphenate("hyphenation", "tlh"), None);
+    }
+}
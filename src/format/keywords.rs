@@ -0,0 +1,156 @@
+use regex::Regex;
+
+use super::FormattedTextInstruction;
+
+/// A project-wide rule that wraps every match of `pattern` in the text style
+/// named `style`, as if the card's author had written the markup tags by hand.
+#[derive(Debug, Clone)]
+pub struct KeywordRule {
+    pub pattern: Regex,
+    pub style: String,
+}
+
+impl KeywordRule {
+    pub fn new(pattern: Regex, style: String) -> KeywordRule {
+        KeywordRule { pattern, style }
+    }
+
+    /// Build a rule that matches any of `words`, but only as whole words.
+    pub fn for_words<S: AsRef<str>>(words: &[S], style: String) -> Result<KeywordRule, regex::Error> {
+        let alternatives: Vec<String> = words.iter().map(|w| whole_word(w.as_ref())).collect();
+        let pattern = Regex::new(&format!("(?:{})", alternatives.join("|")))?;
+        Ok(KeywordRule::new(pattern, style))
+    }
+}
+
+// A pattern for `word` with a word boundary on each end that's a word
+// character. A `\b` next to something like the `+` in "+1/+1" would only
+// match when the word follows a letter or digit.
+fn whole_word(word: &str) -> String {
+    let is_word_char = |ch: char| ch.is_alphanumeric() || ch == '_';
+    let start = if word.chars().next().is_some_and(is_word_char) { r"\b" } else { "" };
+    let end = if word.chars().next_back().is_some_and(is_word_char) { r"\b" } else { "" };
+    format!("{}{}{}", start, regex::escape(word), end)
+}
+
+pub fn apply(instructions: Vec<FormattedTextInstruction>, rules: &[KeywordRule]) -> Vec<FormattedTextInstruction> {
+    if rules.is_empty() {
+        return instructions;
+    }
+
+    let mut styled_instructions = Vec::with_capacity(instructions.len());
+
+    for instruction in instructions {
+        match instruction {
+            FormattedTextInstruction::AddText(text) => {
+                let mut position = 0usize;
+                while let Some((start, end, style)) = next_match(&text, position, rules) {
+                    if start > position {
+                        styled_instructions.push(FormattedTextInstruction::AddText(text[position..start].to_string()));
+                    }
+                    styled_instructions.push(FormattedTextInstruction::PushStyle(style.to_string()));
+                    styled_instructions.push(FormattedTextInstruction::AddText(text[start..end].to_string()));
+                    styled_instructions.push(FormattedTextInstruction::PopStyle(style.to_string()));
+                    position = end;
+                }
+                if position < text.len() {
+                    styled_instructions.push(FormattedTextInstruction::AddText(text[position..].to_string()));
+                }
+            },
+            other => styled_instructions.push(other),
+        }
+    }
+
+    styled_instructions
+}
+
+// Find the leftmost non-empty match of any rule at or after `position`. When
+// two rules match at the same place, the one declared first wins.
+fn next_match<'r>(text: &str, position: usize, rules: &'r [KeywordRule]) -> Option<(usize, usize, &'r str)> {
+    rules
+        .iter()
+        .filter_map(|rule|
+            first_non_empty_match(&rule.pattern, text, position)
+                .map(|(start, end)| (start, end, rule.style.as_str()))
+        )
+        .min_by_key(|(start, _, _)| *start)
+}
+
+// Search the whole text, starting at `position`, so that anchors like `\b`
+// and `^` still see what comes before it
+fn first_non_empty_match(pattern: &Regex, text: &str, position: usize) -> Option<(usize, usize)> {
+    let mut search_from = position;
+    while search_from <= text.len() {
+        let found = pattern.find_at(text, search_from)?;
+        if !found.is_empty() {
+            return Some((found.start(), found.end()));
+        }
+        // Step past the empty match to the next character
+        search_from = found.start() + text[found.start()..].chars().next().map_or(1, |ch| ch.len_utf8());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use crate::format::FormattedTextInstruction;
+
+    use super::{KeywordRule, apply};
+
+    #[test]
+    fn it_wraps_keywords_in_styles() -> () {
+        let rules = vec![
+            KeywordRule::for_words(&["Flying", "First Strike"], "b".to_string()).unwrap(),
+            KeywordRule::for_words(&["+1/+1"], "green".to_string()).unwrap(),
+        ];
+        let instructions = vec![
+            FormattedTextInstruction::AddText("Flying, first strike. Gets +1/+1 for each ".to_string()),
+            FormattedTextInstruction::PushStyle("i".to_string()),
+            FormattedTextInstruction::AddText("First Strike".to_string()),
+            FormattedTextInstruction::PopStyle("i".to_string()),
+            FormattedTextInstruction::AddText(" creature. Flyingfish are unaffected.".to_string()),
+            FormattedTextInstruction::AddText("+1/+1 counters stay.".to_string()),
+        ];
+
+        assert_eq!(
+            apply(instructions, &rules),
+            vec![
+                FormattedTextInstruction::PushStyle("b".to_string()),
+                FormattedTextInstruction::AddText("Flying".to_string()),
+                FormattedTextInstruction::PopStyle("b".to_string()),
+                FormattedTextInstruction::AddText(", first strike. Gets ".to_string()),
+                FormattedTextInstruction::PushStyle("green".to_string()),
+                FormattedTextInstruction::AddText("+1/+1".to_string()),
+                FormattedTextInstruction::PopStyle("green".to_string()),
+                FormattedTextInstruction::AddText(" for each ".to_string()),
+                FormattedTextInstruction::PushStyle("i".to_string()),
+                FormattedTextInstruction::PushStyle("b".to_string()),
+                FormattedTextInstruction::AddText("First Strike".to_string()),
+                FormattedTextInstruction::PopStyle("b".to_string()),
+                FormattedTextInstruction::PopStyle("i".to_string()),
+                FormattedTextInstruction::AddText(" creature. Flyingfish are unaffected.".to_string()),
+                FormattedTextInstruction::PushStyle("green".to_string()),
+                FormattedTextInstruction::AddText("+1/+1".to_string()),
+                FormattedTextInstruction::PopStyle("green".to_string()),
+                FormattedTextInstruction::AddText(" counters stay.".to_string()),
+            ]
+        )
+    }
+
+    #[test]
+    fn it_only_matches_words_after_a_previous_match_at_word_boundaries() -> () {
+        let rules = vec![KeywordRule::new(Regex::new(r"fly\B|\bfly\b").unwrap(), "b".to_string())];
+
+        assert_eq!(
+            apply(vec![FormattedTextInstruction::AddText("flyfly".to_string())], &rules),
+            vec![
+                FormattedTextInstruction::PushStyle("b".to_string()),
+                FormattedTextInstruction::AddText("fly".to_string()),
+                FormattedTextInstruction::PopStyle("b".to_string()),
+                FormattedTextInstruction::AddText("fly".to_string()),
+            ]
+        )
+    }
+}
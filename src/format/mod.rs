@@ -1,14 +1,180 @@
+use miette::{Diagnostic, SourceSpan};
+use thiserror::Error;
+
+pub mod glossary;
+pub mod hyphenate;
+pub mod keywords;
 mod parser;
 
+// Tags that take their value as positional arguments (e.g. `<color red>`)
+// rather than naming a text style defined by the project.
+const ARGUMENT_TAGS: &[&str] = &["color", "background", "font", "size", "weight"];
+
 #[derive(PartialEq, Eq, Debug)]
 pub enum FormattedTextInstruction {
     AddText(String),
     PushStyle(String),
+    PushStyleWithArguments(StyleTag),
     PopStyle(String),
     InsertPlaceholder(String),
+    StartListItem(ListMarker),
+    EndListItem,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ListMarker {
+    Bullet,
+    Numbered(String),
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct StyleTag {
+    pub name: String,
+    pub arguments: Vec<String>,
+    pub attributes: Vec<(String, String)>,
 }
 
-pub fn parse(text: &str) -> Vec<FormattedTextInstruction> {
-    let parser = parser::Parser::new(text);
+impl StyleTag {
+    pub fn takes_arguments(&self) -> bool {
+        ARGUMENT_TAGS.contains(&self.name.as_str())
+    }
+
+    // Split the raw text following a tag's name into positional arguments
+    // and key=value attributes. Values may be double-quoted to include
+    // whitespace. Returns None if the result isn't a sensible tag, which
+    // includes positional arguments on anything other than ARGUMENT_TAGS.
+    fn from_parts(name: &str, raw_arguments: &str) -> Option<StyleTag> {
+        let mut tag = StyleTag { name: name.to_string(), arguments: vec![], attributes: vec![] };
+        let mut chars = raw_arguments.chars().peekable();
+
+        loop {
+            while chars.next_if(|ch| ch.is_whitespace()).is_some() {}
+            let token = match chars.peek() {
+                None => break,
+                Some('"') => {
+                    chars.next();
+                    let quoted: String = chars.by_ref().take_while(|ch| *ch != '"').collect();
+                    tag.arguments.push(quoted);
+                    continue;
+                },
+                Some(_) => {
+                    let mut word = String::new();
+                    while let Some(ch) = chars.next_if(|ch| !ch.is_whitespace() && *ch != '=' && *ch != '"') {
+                        word.push(ch);
+                    }
+                    word
+                },
+            };
+
+            if chars.next_if_eq(&'=').is_some() {
+                if token.is_empty() {
+                    return None;
+                }
+                let value: String = if chars.next_if_eq(&'"').is_some() {
+                    chars.by_ref().take_while(|ch| *ch != '"').collect()
+                } else {
+                    let mut value = String::new();
+                    while let Some(ch) = chars.next_if(|ch| !ch.is_whitespace()) {
+                        value.push(ch);
+                    }
+                    value
+                };
+                tag.attributes.push((token, value));
+            } else if token.is_empty() {
+                return None;
+            } else {
+                tag.arguments.push(token);
+            }
+        }
+
+        if !tag.arguments.is_empty() && !tag.takes_arguments() {
+            None
+        } else {
+            Some(tag)
+        }
+    }
+}
+
+// Read the markup in a piece of text. Lines starting with "- " or "1. " are
+// only treated as list items when `lists` is set.
+pub fn parse(text: &str, lists: bool) -> Vec<FormattedTextInstruction> {
+    let parser = parser::Parser::new(text).with_lists(lists);
     parser.parse()
 }
+
+// Like `parse`, but instead of quietly treating malformed tags as text and
+// leaving unbalanced tags for the renderer to sort out, report them.
+pub fn parse_strict(text: &str, lists: bool) -> Result<Vec<FormattedTextInstruction>, MarkupError> {
+    let parser = parser::Parser::new(text).with_lists(lists);
+    let (instructions, problems) = parser.parse_with_problems();
+
+    if problems.is_empty() {
+        Ok(instructions)
+    } else {
+        Err(MarkupError {
+            problems: problems.into_iter().map(|problem| MarkupProblem::from_parser(text, problem)).collect(),
+        })
+    }
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[error("found {count} problem(s) with the markup in this text", count = .problems.len())]
+pub struct MarkupError {
+    #[related]
+    pub problems: Vec<MarkupProblem>,
+}
+
+#[derive(Error, Diagnostic, Debug)]
+pub enum MarkupProblem {
+    #[error("malformed tag")]
+    #[diagnostic(help("use \\< to write a literal '<'"))]
+    MalformedTag {
+        #[source_code]
+        text: String,
+        #[label("this is not a valid tag")]
+        span: SourceSpan,
+    },
+    #[error("unexpected closing tag </{name}>")]
+    UnexpectedCloseTag {
+        name: String,
+        #[source_code]
+        text: String,
+        #[label("there is no open tag to close here")]
+        span: SourceSpan,
+    },
+    #[error("mismatched tags: expected </{expected}>, but found </{found}>")]
+    MismatchedCloseTag {
+        expected: String,
+        found: String,
+        #[source_code]
+        text: String,
+        #[label("<{expected}> opened here")]
+        opened_at: SourceSpan,
+        #[label("but </{found}> closed here")]
+        closed_at: SourceSpan,
+    },
+    #[error("unclosed tag <{name}>")]
+    UnclosedTag {
+        name: String,
+        #[source_code]
+        text: String,
+        #[label("opened here, but never closed")]
+        span: SourceSpan,
+    },
+}
+
+impl MarkupProblem {
+    fn from_parser(text: &str, problem: parser::Problem) -> MarkupProblem {
+        let text = text.to_string();
+        match problem {
+            parser::Problem::MalformedTag(span) =>
+                MarkupProblem::MalformedTag { text, span: span.into() },
+            parser::Problem::UnexpectedCloseTag(name, span) =>
+                MarkupProblem::UnexpectedCloseTag { name, text, span: span.into() },
+            parser::Problem::MismatchedCloseTag { expected, found, opened_at, closed_at } =>
+                MarkupProblem::MismatchedCloseTag { expected, found, text, opened_at: opened_at.into(), closed_at: closed_at.into() },
+            parser::Problem::UnclosedTag(name, span) =>
+                MarkupProblem::UnclosedTag { name, text, span: span.into() },
+        }
+    }
+}
@@ -1,12 +1,46 @@
-use super::FormattedTextInstruction;
+use std::ops::Range;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{FormattedTextInstruction, StyleTag, ListMarker};
 
 const DEFAULT_TAG_BUFFER_SIZE: usize = 24;
 
+lazy_static! {
+    static ref LIST_ITEM_MARKER: Regex = Regex::new(r#"\A(?:(-)|([0-9]+\.)) "#).unwrap();
+}
+
 pub(super) struct Parser<'a> {
     text: &'a str,
     state: ParserState,
     current_text_buffer: String,
     current_tag_buffer: String,
+    current_arguments_buffer: String,
+    current_tag_start: usize,
+    open_tags: Vec<(String, Range<usize>)>,
+    problems: Vec<Problem>,
+    // Whether lines starting with "- " or "1. " are list items
+    lists: bool,
+    in_list_item: bool,
+    skip_until: usize,
+    current_escape_start: usize,
+}
+
+// Something wrong with the markup, located by its byte range in the text.
+// These are only reported in strict mode; otherwise malformed tags are
+// treated as text and mismatched tags are dealt with by the renderer.
+#[derive(PartialEq, Eq, Debug)]
+pub(super) enum Problem {
+    MalformedTag(Range<usize>),
+    UnexpectedCloseTag(String, Range<usize>),
+    MismatchedCloseTag {
+        expected: String,
+        found: String,
+        opened_at: Range<usize>,
+        closed_at: Range<usize>,
+    },
+    UnclosedTag(String, Range<usize>),
 }
 
 impl<'a> Parser<'a> {
@@ -16,19 +50,72 @@ impl<'a> Parser<'a> {
             state: ParserState::ReadingText,
             current_text_buffer: String::new(),
             current_tag_buffer: String::with_capacity(DEFAULT_TAG_BUFFER_SIZE),
+            current_arguments_buffer: String::new(),
+            current_tag_start: 0,
+            open_tags: vec![],
+            problems: vec![],
+            lists: false,
+            in_list_item: false,
+            skip_until: 0,
+            current_escape_start: 0,
         }
     }
 
-    pub(super) fn parse(mut self) -> Vec<FormattedTextInstruction> {
+    pub(super) fn with_lists(mut self, lists: bool) -> Parser<'a> {
+        self.lists = lists;
+        self
+    }
+
+    pub(super) fn parse(self) -> Vec<FormattedTextInstruction> {
+        self.parse_with_problems().0
+    }
+
+    pub(super) fn parse_with_problems(mut self) -> (Vec<FormattedTextInstruction>, Vec<Problem>) {
         let mut instructions = vec![];
 
-        for ch in self.text.chars() {
+        for (idx, ch) in self.text.char_indices() {
+            if idx < self.skip_until {
+                continue;
+            }
+
+            let at_line_start = idx == 0 || self.text[..idx].ends_with('\n');
+            if self.lists && at_line_start && matches!(self.state, ParserState::ReadingText) && (ch == '-' || ch.is_ascii_digit()) {
+                if self.start_list_item(idx, &mut instructions) {
+                    continue;
+                }
+            }
+
             match self.state {
                 ParserState::ReadingText => match ch {
-                    '<' => self.state = ParserState::ReadingStyleTag,
+                    '\n' if self.in_list_item => {
+                        // The end of the line is the end of the list item
+                        if !self.current_text_buffer.is_empty() {
+                            instructions.push(FormattedTextInstruction::AddText(self.current_text_buffer));
+                            self.current_text_buffer = String::new();
+                        }
+                        instructions.push(FormattedTextInstruction::EndListItem);
+                        self.in_list_item = false;
+                    },
+                    '<' => {
+                        self.current_tag_start = idx;
+                        self.state = ParserState::ReadingStyleTag;
+                    },
                     ':' => self.state = ParserState::ReadingPlaceholderSigil,
+                    '\\' => {
+                        self.current_escape_start = idx;
+                        self.state = ParserState::ReadingEscapedCharacter;
+                    },
                     _ => self.current_text_buffer.push(ch),
                 },
+                ParserState::ReadingEscapedCharacter => {
+                    // Only markup characters can be escaped. A backslash
+                    // before anything else is just a backslash.
+                    if !matches!(ch, '<' | '>' | ':' | '\\') && !self.escapes_list_marker(idx, ch) {
+                        self.current_text_buffer.push('\\');
+                    }
+                    self.current_text_buffer.push(ch);
+                    self.state = ParserState::ReadingText;
+                },
                 ParserState::ReadingStyleTag => match ch {
                     ('A' ..= 'Z') | ('a' ..= 'z') | ('0' ..= '9') | '.' | '_' | '-' => {
                         self.current_tag_buffer.push(ch);
@@ -38,6 +125,7 @@ impl<'a> Parser<'a> {
                     _ => {
                         // Abandon parsing a tag. Push the '<' we've already
                         // read and the character we're looking at.
+                        self.problems.push(Problem::MalformedTag(self.current_tag_start..(idx + ch.len_utf8())));
                         self.current_text_buffer.push('<');
                         self.current_text_buffer.push(ch);
                         self.state = ParserState::ReadingText;
@@ -53,14 +141,23 @@ impl<'a> Parser<'a> {
                             instructions.push(FormattedTextInstruction::AddText(self.current_text_buffer));
                             self.current_text_buffer = String::new();
                         }
+                        self.open_tags.push((self.current_tag_buffer.clone(), self.current_tag_start..(idx + 1)));
                         instructions.push(FormattedTextInstruction::PushStyle(self.current_tag_buffer));
                         self.current_tag_buffer = String::with_capacity(DEFAULT_TAG_BUFFER_SIZE);
                         self.state = ParserState::ReadingText;
                     },
+                    _ if ch.is_whitespace() => {
+                        // The tag name is followed by arguments and/or
+                        // attributes, which we'll make sense of once we've
+                        // read the whole tag.
+                        self.current_arguments_buffer.push(ch);
+                        self.state = ParserState::ReadingStyleTagArguments { in_quotes: false };
+                    },
                     _ => {
                         // Abandon parsing a tag. Push the '<' we've already
                         // read, the "tag name" we've read so far, and the
                         // character we're looking at.
+                        self.problems.push(Problem::MalformedTag(self.current_tag_start..(idx + ch.len_utf8())));
                         self.current_text_buffer.push('<');
                         self.current_text_buffer.push_str(&self.current_tag_buffer);
                         self.current_text_buffer.push(ch);
@@ -68,6 +165,43 @@ impl<'a> Parser<'a> {
                         self.state = ParserState::ReadingText;
                     },
                 },
+                ParserState::ReadingStyleTagArguments { in_quotes } => match ch {
+                    '"' => {
+                        self.current_arguments_buffer.push(ch);
+                        self.state = ParserState::ReadingStyleTagArguments { in_quotes: !in_quotes };
+                    },
+                    '>' if !in_quotes => {
+                        match StyleTag::from_parts(&self.current_tag_buffer, &self.current_arguments_buffer) {
+                            Some(tag) => {
+                                // Finish reading the tag
+                                if !self.current_text_buffer.is_empty() {
+                                    instructions.push(FormattedTextInstruction::AddText(self.current_text_buffer));
+                                    self.current_text_buffer = String::new();
+                                }
+                                self.open_tags.push((tag.name.clone(), self.current_tag_start..(idx + 1)));
+                                if tag.arguments.is_empty() && tag.attributes.is_empty() {
+                                    instructions.push(FormattedTextInstruction::PushStyle(tag.name));
+                                } else {
+                                    instructions.push(FormattedTextInstruction::PushStyleWithArguments(tag));
+                                }
+                            },
+                            None => {
+                                // The arguments don't make sense for this
+                                // tag, so it isn't really a tag. Push
+                                // everything we've read back as text.
+                                self.problems.push(Problem::MalformedTag(self.current_tag_start..(idx + 1)));
+                                self.current_text_buffer.push('<');
+                                self.current_text_buffer.push_str(&self.current_tag_buffer);
+                                self.current_text_buffer.push_str(&self.current_arguments_buffer);
+                                self.current_text_buffer.push(ch);
+                            },
+                        }
+                        self.current_tag_buffer = String::with_capacity(DEFAULT_TAG_BUFFER_SIZE);
+                        self.current_arguments_buffer = String::new();
+                        self.state = ParserState::ReadingText;
+                    },
+                    _ => self.current_arguments_buffer.push(ch),
+                },
                 ParserState::ReadingStyleCloseTag => match ch {
                     ('A' ..= 'Z') | ('a' ..= 'z') | ('0' ..= '9') | '.' | '_' | '-' => {
                         self.current_tag_buffer.push(ch)
@@ -75,6 +209,7 @@ impl<'a> Parser<'a> {
                     '>' => {
                         if self.current_tag_buffer.is_empty() {
                             // Empty "close tags" ("</>") aren't actually tags
+                            self.problems.push(Problem::MalformedTag(self.current_tag_start..(idx + 1)));
                             self.current_text_buffer.push_str("</>");
                             self.state = ParserState::ReadingText;
                         } else {
@@ -83,6 +218,7 @@ impl<'a> Parser<'a> {
                                 instructions.push(FormattedTextInstruction::AddText(self.current_text_buffer));
                                 self.current_text_buffer = String::new();
                             }
+                            self.close_tag(self.current_tag_start..(idx + 1));
                             instructions.push(FormattedTextInstruction::PopStyle(self.current_tag_buffer));
                             self.current_tag_buffer = String::with_capacity(DEFAULT_TAG_BUFFER_SIZE);
                             self.state = ParserState::ReadingText;
@@ -92,6 +228,7 @@ impl<'a> Parser<'a> {
                         // Abandon parsing a tag. Push the '</' we've already
                         // read, the "tag name" we've read so far, and the
                         // character we're looking at.
+                        self.problems.push(Problem::MalformedTag(self.current_tag_start..(idx + ch.len_utf8())));
                         self.current_text_buffer.push_str("</");
                         self.current_text_buffer.push_str(&self.current_tag_buffer);
                         self.current_text_buffer.push(ch);
@@ -133,27 +270,125 @@ impl<'a> Parser<'a> {
             }
         }
 
+        // If the text ended in the middle of a tag, placeholder, or escape
+        // sequence, treat whatever we've read of it as text.
+        match self.state {
+            ParserState::ReadingText => {},
+            ParserState::ReadingEscapedCharacter => self.current_text_buffer.push('\\'),
+            ParserState::ReadingStyleTag | ParserState::ReadingStyleOpenTag | ParserState::ReadingStyleTagArguments { .. } => {
+                self.problems.push(Problem::MalformedTag(self.current_tag_start..self.text.len()));
+                self.current_text_buffer.push('<');
+                self.current_text_buffer.push_str(&self.current_tag_buffer);
+                self.current_text_buffer.push_str(&self.current_arguments_buffer);
+            },
+            ParserState::ReadingStyleCloseTag => {
+                self.problems.push(Problem::MalformedTag(self.current_tag_start..self.text.len()));
+                self.current_text_buffer.push_str("</");
+                self.current_text_buffer.push_str(&self.current_tag_buffer);
+            },
+            ParserState::ReadingPlaceholderSigil => {
+                self.current_text_buffer.push(':');
+                self.current_text_buffer.push_str(&self.current_tag_buffer);
+            },
+        }
+
         if !self.current_text_buffer.is_empty() {
             instructions.push(FormattedTextInstruction::AddText(self.current_text_buffer));
         }
 
-        instructions
+        if self.in_list_item {
+            instructions.push(FormattedTextInstruction::EndListItem);
+        }
+
+        for (name, opened_at) in self.open_tags.drain(..).rev() {
+            self.problems.push(Problem::UnclosedTag(name, opened_at));
+        }
+
+        (instructions, self.problems)
+    }
+
+    // If the line starting at `idx` begins with a list item marker ("- " or
+    // "1. "), start a new list item and skip over the marker.
+    fn start_list_item(&mut self, idx: usize, instructions: &mut Vec<FormattedTextInstruction>) -> bool {
+        let captures = match LIST_ITEM_MARKER.captures(&self.text[idx..]) {
+            Some(captures) => captures,
+            None => return false,
+        };
+        let marker = match captures.get(2) {
+            Some(number) => ListMarker::Numbered(number.as_str().to_string()),
+            None => ListMarker::Bullet,
+        };
+
+        // The line break before the list item is implied by the item itself
+        if self.current_text_buffer.ends_with('\n') {
+            self.current_text_buffer.pop();
+        }
+        if !self.current_text_buffer.is_empty() {
+            instructions.push(FormattedTextInstruction::AddText(self.current_text_buffer.clone()));
+            self.current_text_buffer = String::new();
+        }
+        if self.in_list_item {
+            instructions.push(FormattedTextInstruction::EndListItem);
+        }
+        instructions.push(FormattedTextInstruction::StartListItem(marker));
+        self.in_list_item = true;
+        self.skip_until = idx + captures.get(0).map_or(0, |m| m.end());
+
+        true
+    }
+
+    // Whether the character at `idx`, which follows a backslash, would
+    // otherwise be part of a list item marker: the "-" of `\- ` or the "." of
+    // `1\. ` at the start of a line
+    fn escapes_list_marker(&self, idx: usize, ch: char) -> bool {
+        if !self.lists || !self.text[(idx + ch.len_utf8())..].starts_with(' ') {
+            return false;
+        }
+        let line_start = self.text[..self.current_escape_start].rfind('\n').map_or(0, |newline| newline + 1);
+        let before_escape = &self.text[line_start..self.current_escape_start];
+        match ch {
+            '-' => before_escape.is_empty(),
+            '.' => !before_escape.is_empty() && before_escape.chars().all(|c| c.is_ascii_digit()),
+            _ => false,
+        }
+    }
+
+    fn close_tag(&mut self, closed_at: Range<usize>) -> () {
+        match self.open_tags.iter().rposition(|(name, _)| *name == self.current_tag_buffer) {
+            Some(position) => {
+                // Anything opened after the tag we're closing should have
+                // been closed first.
+                for (name, opened_at) in self.open_tags.drain((position + 1)..).rev() {
+                    self.problems.push(Problem::MismatchedCloseTag {
+                        expected: name,
+                        found: self.current_tag_buffer.clone(),
+                        opened_at,
+                        closed_at: closed_at.clone(),
+                    });
+                }
+                self.open_tags.pop();
+            },
+            None => self.problems.push(Problem::UnexpectedCloseTag(self.current_tag_buffer.clone(), closed_at)),
+        }
     }
 }
 
+#[derive(Clone, Copy)]
 enum ParserState {
     ReadingText,
+    ReadingEscapedCharacter,
     ReadingStyleTag,
     ReadingStyleOpenTag,
+    ReadingStyleTagArguments { in_quotes: bool },
     ReadingStyleCloseTag,
     ReadingPlaceholderSigil,
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::format::FormattedTextInstruction;
+    use crate::format::{FormattedTextInstruction, StyleTag, ListMarker};
 
-    use super::Parser;
+    use super::{Parser, Problem};
 
     #[test]
     fn it_finds_well_formed_elements() -> () {
@@ -178,6 +413,119 @@ mod tests {
         )
     }
 
+    #[test]
+    fn it_reads_tag_arguments_and_attributes() -> () {
+        let text = r#"<color red>Red</color>, <font "Fira Code">code</font>, <rules size=9pt color="dark gray">rules</rules>"#;
+        let parser = Parser::new(text);
+        let formatting_instructions = parser.parse();
+
+        assert_eq!(
+            formatting_instructions,
+            vec![
+                FormattedTextInstruction::PushStyleWithArguments(StyleTag {
+                    name: "color".to_string(),
+                    arguments: vec!["red".to_string()],
+                    attributes: vec![],
+                }),
+                FormattedTextInstruction::AddText("Red".to_string()),
+                FormattedTextInstruction::PopStyle("color".to_string()),
+                FormattedTextInstruction::AddText(", ".to_string()),
+                FormattedTextInstruction::PushStyleWithArguments(StyleTag {
+                    name: "font".to_string(),
+                    arguments: vec!["Fira Code".to_string()],
+                    attributes: vec![],
+                }),
+                FormattedTextInstruction::AddText("code".to_string()),
+                FormattedTextInstruction::PopStyle("font".to_string()),
+                FormattedTextInstruction::AddText(", ".to_string()),
+                FormattedTextInstruction::PushStyleWithArguments(StyleTag {
+                    name: "rules".to_string(),
+                    arguments: vec![],
+                    attributes: vec![
+                        ("size".to_string(), "9pt".to_string()),
+                        ("color".to_string(), "dark gray".to_string()),
+                    ],
+                }),
+                FormattedTextInstruction::AddText("rules".to_string()),
+                FormattedTextInstruction::PopStyle("rules".to_string()),
+            ]
+        )
+    }
+
+    #[test]
+    fn it_unescapes_markup_characters() -> () {
+        let text = r"A literal \<b\> and \:x: and \\, but C:\path stays put:";
+        let parser = Parser::new(text);
+        let instructions = parser.parse();
+
+        assert_eq!(
+            instructions,
+            vec![
+                FormattedTextInstruction::AddText(r"A literal <b> and :x: and \, but C:\path stays put:".to_string()),
+            ]
+        )
+    }
+
+    #[test]
+    fn it_reports_malformed_and_unbalanced_tags() -> () {
+        let text = "<b>bold <i>both</b> x < 3 </u> <u>under <rules";
+        let parser = Parser::new(text);
+        let (_, problems) = parser.parse_with_problems();
+
+        assert_eq!(
+            problems,
+            vec![
+                Problem::MismatchedCloseTag {
+                    expected: "i".to_string(),
+                    found: "b".to_string(),
+                    opened_at: 8..11,
+                    closed_at: 15..19,
+                },
+                Problem::MalformedTag(22..24),
+                Problem::UnexpectedCloseTag("u".to_string(), 26..30),
+                Problem::MalformedTag(40..46),
+                Problem::UnclosedTag("u".to_string(), 31..34),
+            ]
+        )
+    }
+
+    #[test]
+    fn it_finds_list_items() -> () {
+        let text = "Choose one:\n- Draw a card.\n- Gain <b>2</b> gold.\n1. First\n10. Tenth\n\\- not an item, nor\\- this\n3\\. Not one either";
+        let parser = Parser::new(text).with_lists(true);
+        let instructions = parser.parse();
+
+        assert_eq!(
+            instructions,
+            vec![
+                FormattedTextInstruction::AddText("Choose one:".to_string()),
+                FormattedTextInstruction::StartListItem(ListMarker::Bullet),
+                FormattedTextInstruction::AddText("Draw a card.".to_string()),
+                FormattedTextInstruction::EndListItem,
+                FormattedTextInstruction::StartListItem(ListMarker::Bullet),
+                FormattedTextInstruction::AddText("Gain ".to_string()),
+                FormattedTextInstruction::PushStyle("b".to_string()),
+                FormattedTextInstruction::AddText("2".to_string()),
+                FormattedTextInstruction::PopStyle("b".to_string()),
+                FormattedTextInstruction::AddText(" gold.".to_string()),
+                FormattedTextInstruction::EndListItem,
+                FormattedTextInstruction::StartListItem(ListMarker::Numbered("1.".to_string())),
+                FormattedTextInstruction::AddText("First".to_string()),
+                FormattedTextInstruction::EndListItem,
+                FormattedTextInstruction::StartListItem(ListMarker::Numbered("10.".to_string())),
+                FormattedTextInstruction::AddText("Tenth".to_string()),
+                FormattedTextInstruction::EndListItem,
+                FormattedTextInstruction::AddText("- not an item, nor\\- this\n3. Not one either".to_string()),
+            ]
+        );
+
+        // Without lists turned on, it's all just text
+        assert_eq!(
+            Parser::new("- Draw a card.\n\\- Discard").parse(),
+            vec![FormattedTextInstruction::AddText("- Draw a card.\n\\- Discard".to_string())],
+        );
+    }
+
     #[test]
     fn it_degrades_invalid_forms_to_text() -> () {
         let text = r###"
@@ -188,6 +536,9 @@ mod tests {
               - empty open tag <>,
               - empty close tag </>
               - empty emoji ::
+              - positional arguments on a named style <rules 9pt>
+              - malformed attribute <rules =9pt>
+              - unterminated tag <color "red>
         "###;
         let parser = Parser::new(text);
         let instructions = parser.parse();
@@ -0,0 +1,213 @@
+use std::str::FromStr;
+
+use miette::miette;
+
+use crate::layout::templates::TemplateContext;
+
+use super::{dimension::{Dimension, DimensionError, Extent}, elements::Bounds, geometry::Zones};
+
+// Properties that position an element relative to something other than the
+// top left corner of the box it's in. Any element with a frame takes them:
+//
+//     rectangle right=10 bottom=10 w=100 h=40
+//     text "{{name}}" { frame center-x=0 y=20 w=400 h=60 }
+//     image "logo" { frame anchor="safe-bottom-right" x=0 y=0 w=80 h=80 }
+//
+// `anchor` picks the point that `x` and `y` are measured from: one of
+// "top-left", "top", "top-right", "left", "center", "right", "bottom-left",
+// "bottom", or "bottom-right". Offsets from an edge point inwards, so
+// `anchor="bottom-right" x=10 y=10` puts the element's bottom right corner 10
+// pixels in from the bottom right corner. Offsets from a center point right
+// or down.
+//
+// The anchor can start with the box it refers to: "card-" for the edge of the
+// card, "cut-" for the cut line, or "safe-" for the safe zone (as set in the
+// layout's geometry). On its own, "card", "cut", or "safe" means the top left
+// corner of that box. Without one, the anchor refers to the box the element
+// is in. Percentages are relative to whichever box the element is anchored
+// to.
+//
+// `right`, `bottom`, `center-x`, and `center-y` measure from the right edge,
+// bottom edge, or center of the same box, and take the place of `x` or `y`.
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Anchoring {
+    #[knuffel(property, str)]
+    pub anchor: Option<Anchor>,
+    #[knuffel(property)]
+    pub right: Option<Dimension>,
+    #[knuffel(property)]
+    pub bottom: Option<Dimension>,
+    #[knuffel(property(name="center-x"))]
+    pub center_x: Option<Dimension>,
+    #[knuffel(property(name="center-y"))]
+    pub center_y: Option<Dimension>,
+}
+
+impl Default for Anchoring {
+    fn default() -> Self {
+        Anchoring { anchor: None, right: None, bottom: None, center_x: None, center_y: None }
+    }
+}
+
+impl Anchoring {
+    pub fn place(&self, x: &Dimension, y: &Dimension, w: &Dimension, h: &Dimension, ctx: &TemplateContext, extent: Extent) -> Result<Bounds, DimensionError> {
+        let anchor = self.anchor.unwrap_or_default();
+
+        // Work out where the box we're anchored to is, relative to the box
+        // the element is in
+        let (reference_x, reference_y, reference) = match anchor.zone.bounds(&extent.zones) {
+            None => (0.0, 0.0, extent),
+            Some(zone) => (
+                zone.x - extent.origin.0,
+                zone.y - extent.origin.1,
+                Extent { w: zone.w, h: zone.h, ..extent },
+            ),
+        };
+
+        let w = w.resolve(ctx, reference.horizontal())?;
+        let h = h.resolve(ctx, reference.vertical())?;
+        let (horizontal, x) = match (&self.center_x, &self.right) {
+            (Some(center_x), _) => (Side::Center, center_x),
+            (None, Some(right)) => (Side::End, right),
+            (None, None) => (anchor.horizontal, x),
+        };
+        let (vertical, y) = match (&self.center_y, &self.bottom) {
+            (Some(center_y), _) => (Side::Center, center_y),
+            (None, Some(bottom)) => (Side::End, bottom),
+            (None, None) => (anchor.vertical, y),
+        };
+        let x = horizontal.place(reference_x, reference.w, w, x.resolve_signed(ctx, reference.horizontal())?);
+        let y = vertical.place(reference_y, reference.h, h, y.resolve_signed(ctx, reference.vertical())?);
+
+        // The result may be above or to the left of the box the element is
+        // in, which clips whatever doesn't fit
+        Ok(Bounds { x, y, w, h })
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Anchor {
+    pub zone: Zone,
+    pub horizontal: Side,
+    pub vertical: Side,
+}
+
+impl Default for Anchor {
+    fn default() -> Self {
+        Anchor { zone: Zone::Parent, horizontal: Side::Start, vertical: Side::Start }
+    }
+}
+
+impl FromStr for Anchor {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (zone, point) = match s.split_once('-') {
+            Some(("card", point)) => (Zone::Card, point),
+            Some(("cut", point)) => (Zone::Cut, point),
+            Some(("safe", point)) => (Zone::Safe, point),
+            _ => match s {
+                "card" => (Zone::Card, "top-left"),
+                "cut" => (Zone::Cut, "top-left"),
+                "safe" => (Zone::Safe, "top-left"),
+                _ => (Zone::Parent, s),
+            },
+        };
+        let (vertical, horizontal) = match point {
+            "top-left" => (Side::Start, Side::Start),
+            "top" => (Side::Start, Side::Center),
+            "top-right" => (Side::Start, Side::End),
+            "left" => (Side::Center, Side::Start),
+            "center" => (Side::Center, Side::Center),
+            "right" => (Side::Center, Side::End),
+            "bottom-left" => (Side::End, Side::Start),
+            "bottom" => (Side::End, Side::Center),
+            "bottom-right" => (Side::End, Side::End),
+            _ => return Err(miette!(r#"Invalid anchor "{}". Expected a point like `"top-left"`, `"center"`, or `"bottom-right"`, optionally starting with `"card-"`, `"cut-"`, or `"safe-"`."#, s)),
+        };
+        Ok(Anchor { zone, horizontal, vertical })
+    }
+}
+
+// The box an anchor refers to
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Zone {
+    Parent,
+    Card,
+    Cut,
+    Safe,
+}
+
+impl Zone {
+    // Where this zone is on the card, or `None` for the element's parent
+    fn bounds(&self, zones: &Zones) -> Option<Bounds> {
+        match self {
+            Zone::Parent => None,
+            Zone::Card => Some(zones.card),
+            Zone::Cut => Some(zones.cut),
+            Zone::Safe => Some(zones.safe),
+        }
+    }
+}
+
+// Which part of a box, along one axis, an offset is measured from
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Side {
+    Start,
+    Center,
+    End,
+}
+
+impl Side {
+    // Where an element of the given size starts, when it's placed `offset`
+    // away from this side of a box
+    fn place(&self, start: f32, length: f32, size: f32, offset: f32) -> f32 {
+        match self {
+            Side::Start => start + offset,
+            Side::Center => start + (length - size) / 2.0 + offset,
+            Side::End => start + length - size - offset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use handlebars::JsonValue as Json;
+
+    use crate::layout::{model::{elements::{Bounds, Element}, testing::{card, card_data, parse_elements}}, templates::{new_registry, TemplateContext}};
+
+    #[test]
+    fn it_anchors_elements_to_card_zones() -> miette::Result<()> {
+        let elements = parse_elements(r#"
+            rectangle right=10 bottom="{{inset}}" w=100 h=40
+            rectangle center-x=0 center-y=-20 w=200 h=100
+            rectangle anchor="safe-bottom-right" x=0 y=0 w="10%" h=80
+            rectangle anchor="cut" x=-5 y=0 w=10 h=10
+            rectangle anchor="card-top" y=5 w=25 h=10
+        "#)?;
+
+        let registry = new_registry();
+        let data = card_data(HashMap::from([("inset", Json::from(20))]))?;
+        let ctx = TemplateContext::new(&registry, &data);
+        // A box at (100, 200) on the card
+        let parent = card().within(&Bounds { x: 100.0, y: 200.0, w: 500.0, h: 600.0 });
+
+        let placed = elements.iter()
+            .map(|element| match element {
+                Element::Rectangle(rect) => rect.bounds(&ctx, parent),
+                other => panic!("unexpected element {:?}", other),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(placed, vec![
+            Bounds { x: 390.0, y: 540.0, w: 100.0, h: 40.0 },
+            Bounds { x: 150.0, y: 230.0, w: 200.0, h: 100.0 },
+            Bounds { x: 582.5, y: 770.0, w: 67.5, h: 80.0 },
+            Bounds { x: -68.0, y: -163.0, w: 10.0, h: 10.0 },
+            Bounds { x: 300.0, y: -195.0, w: 25.0, h: 10.0 },
+        ]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,419 @@
+use std::fmt::Display;
+
+use knuffel::{ast::{Literal, TypeName}, decode::{Context, Kind}, errors::DecodeError, span::Spanned, traits::ErrorSpan};
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::layout::templates::{TemplateAwareString, TemplateContext, TemplateError};
+
+use super::{elements::Bounds, geometry::Zones};
+
+// A length or coordinate in a layout. It can be a plain number of pixels:
+//
+//     rectangle x=10 y=10 w=100 h=20.5
+//
+// or a string holding an arithmetic expression, which may use templates to
+// pull in card data:
+//
+//     rectangle x=10 y=10 w="{{mul health 12}}" h=20
+//     rectangle x="100 - {{cost}} * 8" y=10 w=40 h="(20 + 4) / 2"
+//
+// Expressions support `+`, `-`, `*`, `/`, `%`, and parentheses. Numbers in
+// an expression may have a unit attached:
+//
+//     rectangle x="3mm" y="0.25in" w="50% - 6pt" h="1.5cm"
+//
+// `mm`, `cm`, `in`, and `pt` are converted to pixels using the layout's DPI,
+// `px` is the same as no unit at all, and `%` is relative to the width (for
+// `x` and `w`) or height (for `y` and `h`) of the enclosing box. A `%` with a
+// space before it is the remainder operator instead.
+//
+// Everything is worked out at full precision, and lengths stay fractional
+// through layout so that several of them add up without drifting. Negative
+// results are treated as 0, except for offsets from an anchor (see
+// `Anchoring`).
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Dimension {
+    Fixed(usize),
+    Expression(TemplateAwareString),
+}
+
+impl Dimension {
+    pub fn resolve(&self, ctx: &TemplateContext, measure: Measure) -> Result<f32, DimensionError> {
+        Ok(self.resolve_signed(ctx, measure)?.max(0.0))
+    }
+
+    // Resolve an offset, which unlike a size or position may be negative
+    pub fn resolve_signed(&self, ctx: &TemplateContext, measure: Measure) -> Result<f32, DimensionError> {
+        match self {
+            Dimension::Fixed(value) => Ok(*value as f32),
+            Dimension::Expression(expression) => {
+                let filled = expression.render(ctx)?;
+                Ok(evaluate_to_pixels(expression.source(), &filled, measure)? as f32)
+            },
+        }
+    }
+
+    // Resolve a whole number that isn't a length, like a count or an index
+    pub fn resolve_count(&self, ctx: &TemplateContext) -> Result<usize, DimensionError> {
+        Ok(self.resolve(ctx, Measure::unitless())?.round() as usize)
+    }
+
+    // A dimension for an already resolved length
+    pub fn pixels(value: f32) -> Dimension {
+        if value >= 0.0 && value.fract() == 0.0 {
+            Dimension::Fixed(value as usize)
+        } else {
+            Dimension::Expression(TemplateAwareString::new(value.to_string()))
+        }
+    }
+
+    // Resolve a dimension that can't depend on card data, like the ones in a
+    // layout's geometry
+    pub fn to_pixels(&self, measure: Measure) -> Result<usize, DimensionError> {
+        match self {
+            Dimension::Fixed(value) => Ok(*value),
+            Dimension::Expression(TemplateAwareString::RawString(expression)) =>
+                Ok(evaluate_to_pixels(expression, expression, measure)?.round().max(0.0) as usize),
+            Dimension::Expression(TemplateAwareString::Template(expression)) =>
+                Err(DimensionError::TemplateNotAllowed(expression.clone())),
+        }
+    }
+}
+
+fn evaluate_to_pixels(source_expression: &str, expression: &str, measure: Measure) -> Result<f64, DimensionError> {
+    evaluate(expression, measure).map_err(|reason| DimensionError::InvalidExpression {
+        source_expression: source_expression.to_string(),
+        expression: expression.to_string(),
+        reason,
+    })
+}
+
+// What units in a dimension are measured against: the DPI for physical
+// units, and the length of the enclosing box along the same axis for
+// percentages. Either may be missing, in which case those units can't be
+// used.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Measure {
+    pub dpi: Option<usize>,
+    pub parent: Option<f32>,
+}
+
+impl Measure {
+    pub fn absolute(dpi: usize) -> Measure {
+        Measure { dpi: Some(dpi), parent: None }
+    }
+
+    // For numbers that aren't lengths at all, like counts
+    pub fn unitless() -> Measure {
+        Measure { dpi: None, parent: None }
+    }
+}
+
+// The size of the box an element is placed in, along with the DPI it's being
+// drawn at. Anchored elements also need to know where the box is on the card
+// (`origin`), and where the card's cut line and safe zone are.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Extent {
+    pub w: f32,
+    pub h: f32,
+    pub dpi: usize,
+    pub origin: (f32, f32),
+    pub zones: Zones,
+}
+
+impl Extent {
+    // The extent of a box at `bounds` inside of this one
+    pub fn within(&self, bounds: &Bounds) -> Extent {
+        Extent {
+            w: bounds.w,
+            h: bounds.h,
+            origin: (self.origin.0 + bounds.x, self.origin.1 + bounds.y),
+            ..*self
+        }
+    }
+
+    // The same box, moved over by dx and down by dy
+    pub fn shifted(&self, dx: f32, dy: f32) -> Extent {
+        Extent {
+            origin: (self.origin.0 + dx, self.origin.1 + dy),
+            ..*self
+        }
+    }
+
+    pub fn horizontal(&self) -> Measure {
+        Measure { dpi: Some(self.dpi), parent: Some(self.w) }
+    }
+
+    pub fn vertical(&self) -> Measure {
+        Measure { dpi: Some(self.dpi), parent: Some(self.h) }
+    }
+}
+
+impl From<usize> for Dimension {
+    fn from(value: usize) -> Self {
+        Dimension::Fixed(value)
+    }
+}
+
+impl Display for Dimension {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Dimension::Fixed(value) => write!(f, "{}", value),
+            Dimension::Expression(expression) => write!(f, "\"{}\"", expression.source()),
+        }
+    }
+}
+
+impl<S> knuffel::DecodeScalar<S> for Dimension where S: ErrorSpan {
+    fn type_check(type_name: &Option<Spanned<TypeName, S>>, ctx: &mut Context<S>) {
+        if let Some(type_name) = type_name {
+            ctx.emit_error(DecodeError::unsupported(type_name, "type annotations are not supported on dimensions"));
+        }
+    }
+
+    fn raw_decode(literal: &Spanned<Literal, S>, _ctx: &mut Context<S>) -> Result<Self, DecodeError<S>> {
+        match **literal {
+            Literal::Int(ref raw_integer) => match raw_integer.try_into() {
+                Ok(value) => Ok(Dimension::Fixed(value)),
+                // Negative numbers only make sense as offsets, which are
+                // resolved the same way as expressions
+                Err(_) => {
+                    let value: i64 = raw_integer.try_into().map_err(|err| DecodeError::conversion(literal, err))?;
+                    Ok(Dimension::Expression(TemplateAwareString::new(value.to_string())))
+                },
+            },
+            Literal::Decimal(ref raw_decimal) => {
+                let value: f64 = raw_decimal.try_into().map_err(|err| DecodeError::conversion(literal, err))?;
+                Ok(Dimension::Expression(TemplateAwareString::new(value.to_string())))
+            },
+            Literal::String(ref expression) => Ok(Dimension::Expression(TemplateAwareString::new(expression.to_string()))),
+            _ => Err(DecodeError::scalar_kind(Kind::Int, literal)),
+        }
+    }
+}
+
+// A small recursive-descent evaluator for the expression grammar:
+//
+//     expression := term (("+" | "-") term)*
+//     term       := factor (("*" | "/" | "%") factor)*
+//     factor     := ("-" | "+") factor | number unit? | "(" expression ")"
+fn evaluate(expression: &str, measure: Measure) -> Result<f64, String> {
+    let mut parser = ExpressionParser { source: expression, position: 0, measure };
+    let value = parser.expression()?;
+    parser.skip_whitespace();
+    if parser.position < expression.len() {
+        return Err(format!("unexpected \"{}\"", &expression[parser.position..]));
+    }
+    if !value.is_finite() {
+        return Err("the result is not a number (is there a division by zero?)".to_string());
+    }
+    Ok(value)
+}
+
+struct ExpressionParser<'a> {
+    source: &'a str,
+    position: usize,
+    measure: Measure,
+}
+
+impl ExpressionParser<'_> {
+    fn expression(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+        loop {
+            match self.peek() {
+                Some('+') => { self.position += 1; value += self.term()?; },
+                Some('-') => { self.position += 1; value -= self.term()?; },
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.factor()?;
+        loop {
+            match self.peek() {
+                Some('*') => { self.position += 1; value *= self.factor()?; },
+                Some('/') => { self.position += 1; value /= self.factor()?; },
+                Some('%') => { self.position += 1; value %= self.factor()?; },
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    fn factor(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some('-') => { self.position += 1; Ok(-self.factor()?) },
+            Some('+') => { self.position += 1; self.factor() },
+            Some('(') => {
+                self.position += 1;
+                let value = self.expression()?;
+                match self.peek() {
+                    Some(')') => { self.position += 1; Ok(value) },
+                    _ => Err("missing \")\"".to_string()),
+                }
+            },
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let rest = &self.source[self.position..];
+                let length = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+                let number = rest[..length].parse::<f64>().map_err(|_| format!("invalid number \"{}\"", &rest[..length]))?;
+                self.position += length;
+                self.unit(number)
+            },
+            Some(_) => Err(format!("expected a number but found \"{}\"", &self.source[self.position..])),
+            None => Err("expected a number but the expression ended".to_string()),
+        }
+    }
+
+    // Convert a number to pixels according to the unit directly after it, if
+    // there is one
+    fn unit(&mut self, number: f64) -> Result<f64, String> {
+        let rest = &self.source[self.position..];
+        let length = if rest.starts_with('%') {
+            1
+        } else {
+            rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len())
+        };
+        let unit = &rest[..length];
+        let pixels_per_inch = || self.measure.dpi
+            .map(|dpi| dpi as f64)
+            .ok_or_else(|| format!("\"{}\" can't be used here, since this isn't a length", unit));
+        let pixels = match unit {
+            "" | "px" => number,
+            "in" => number * pixels_per_inch()?,
+            "cm" => number * pixels_per_inch()? / 2.54,
+            "mm" => number * pixels_per_inch()? / 25.4,
+            "pt" => number * pixels_per_inch()? / 72.0,
+            "%" => match self.measure.parent {
+                Some(parent) => number * (parent as f64) / 100.0,
+                None => return Err("percentages can't be used here, since there's nothing for them to be relative to".to_string()),
+            },
+            unit => return Err(format!("unknown unit \"{}\" (expected one of px, mm, cm, in, pt, or %)", unit)),
+        };
+        self.position += length;
+        Ok(pixels)
+    }
+
+    // Skip whitespace and return the next character without consuming it
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.source[self.position..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.source[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+}
+
+#[derive(Error, Diagnostic, Debug)]
+pub enum DimensionError {
+    #[error(transparent)]
+    TemplateError(#[from] TemplateError),
+    #[error("dimension \"{0}\" can't use templates here")]
+    TemplateNotAllowed(String),
+    #[error("invalid dimension \"{expression}\" (from \"{source_expression}\"): {reason}")]
+    InvalidExpression {
+        source_expression: String,
+        expression: String,
+        reason: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use handlebars::JsonValue as Json;
+
+    use crate::layout::{model::{elements::Element, testing::{card, card_data, parse_elements}}, templates::new_registry};
+
+    use super::*;
+
+    #[test]
+    fn it_resolves_dimension_expressions() -> miette::Result<()> {
+        let registry = new_registry();
+        let data = card_data(HashMap::from([("cost", Json::from(3))]))?;
+        let ctx = TemplateContext::new(&registry, &data);
+        let measure = Measure { dpi: Some(300), parent: Some(200.0) };
+        let resolve = |expression: &str| Dimension::Expression(TemplateAwareString::new(expression.to_string())).resolve(&ctx, measure);
+
+        assert_eq!(Dimension::Fixed(12).resolve(&ctx, measure)?, 12.0);
+        assert_eq!(resolve("{{mul cost 40}}")?, 120.0);
+        assert_eq!(resolve("100 - {{cost}} * 8")?, 76.0);
+        assert_eq!(resolve("(20 + 5) / 2")?, 12.5);
+        assert_eq!(resolve("-(4 % 3) + 1.25")?, 0.25);
+        assert_eq!(resolve("2 - {{cost}} * 8")?, 0.0);
+        assert!(matches!(resolve("10 +"), Err(DimensionError::InvalidExpression { .. })));
+        assert!(matches!(resolve("10 / (2 - 2)"), Err(DimensionError::InvalidExpression { .. })));
+        assert!(matches!(resolve("{{cost}}ft"), Err(DimensionError::InvalidExpression { .. })));
+
+        // Units
+        assert_eq!(resolve("{{cost}}px")?, 3.0);
+        assert_eq!(resolve("63mm")?.round(), 744.0);
+        assert_eq!(resolve("0.25in")?, 75.0);
+        assert_eq!(resolve("12pt + 2.5cm")?.round(), 345.0);
+        assert_eq!(resolve("50% - 10")?, 90.0);
+        assert_eq!(resolve("50 % 7")?, 1.0);
+        assert!(matches!(
+            Dimension::Expression(TemplateAwareString::new("10%".to_string())).to_pixels(Measure::absolute(300)),
+            Err(DimensionError::InvalidExpression { .. }),
+        ));
+        assert!(matches!(
+            Dimension::Expression(TemplateAwareString::new("{{cost}}mm".to_string())).to_pixels(Measure::absolute(300)),
+            Err(DimensionError::TemplateNotAllowed(_)),
+        ));
+        assert_eq!(Dimension::Expression(TemplateAwareString::new("2in".to_string())).to_pixels(Measure::absolute(300))?, 600);
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_dimensions_from_layouts() -> miette::Result<()> {
+        let elements = parse_elements(r#"
+            rectangle x=5 y=-6 w="{{width}} + 1" h=2.5
+        "#)?;
+
+        let registry = new_registry();
+        let data = card_data(HashMap::from([("width", Json::from(7))]))?;
+        let ctx = TemplateContext::new(&registry, &data);
+        match elements.as_slice() {
+            [Element::Rectangle(rect)] => {
+                assert_eq!(rect.x, Dimension::Fixed(5));
+                assert_eq!(rect.y, Dimension::Expression(TemplateAwareString::RawString("-6".to_string())));
+                assert_eq!(rect.w, Dimension::Expression(TemplateAwareString::Template("{{width}} + 1".to_string())));
+                assert_eq!(rect.h, Dimension::Expression(TemplateAwareString::RawString("2.5".to_string())));
+
+                let measure = card().horizontal();
+                assert_eq!(rect.y.resolve_signed(&ctx, measure)?, -6.0);
+                assert_eq!(rect.w.resolve(&ctx, measure)?, 8.0);
+                assert_eq!(rect.h.resolve(&ctx, measure)?, 2.5);
+            },
+            other => panic!("unexpected elements {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_adds_up_physical_lengths_without_drift() -> miette::Result<()> {
+        let registry = new_registry();
+        let data = card_data(HashMap::<&str, Json>::new())?;
+        let ctx = TemplateContext::new(&registry, &data);
+        let resolve = |expression: &str| Dimension::Expression(TemplateAwareString::new(expression.to_string())).resolve(&ctx, Measure::absolute(300));
+
+        // 1mm is about 11.8px at 300 DPI, so rounding each offset to a whole
+        // pixel would put ten of them 2px past 10mm
+        let offset = resolve("1mm")?;
+        let mut extent = card();
+        for _ in 0..10 {
+            extent = extent.within(&Bounds { x: offset, y: offset, w: extent.w - offset, h: extent.h - offset });
+        }
+        let expected = resolve("10mm")?;
+        assert!((extent.origin.0 - expected).abs() < 0.01, "{} != {}", extent.origin.0, expected);
+        assert!((extent.origin.1 - expected).abs() < 0.01, "{} != {}", extent.origin.1, expected);
+        assert_eq!(extent.origin.0.round(), 118.0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,328 @@
+use std::str::FromStr;
+
+use barcoders::sym::{code128::Code128, ean13::EAN13};
+use miette::{miette, Diagnostic};
+use qrcode::EcLevel;
+use thiserror::Error;
+
+use crate::layout::{model::styles::{color::ColorRef, only_if::OnlyIf}, templates::{TemplateAwareString, TemplateContext}};
+
+use super::Frame;
+
+// A QR code, drawn as squares scaled to fit the frame:
+//
+//     qrcode "{{video}}" {
+//         frame anchor="safe-bottom-right" w=150 h=150
+//         error-correction "high"
+//         foreground "black"
+//         background "white"
+//     }
+//
+// `error-correction` is one of "low", "medium" (the default), "quartile", or
+// "high". Higher levels survive more damage but need more squares. The
+// foreground defaults to black, and there's no background unless one is
+// given. `quiet-zone` sets the blank margin around the code, in modules (the
+// code's squares), and defaults to the 4 that scanners expect.
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct QrCode {
+    #[knuffel(argument, str)]
+    pub contents: TemplateAwareString,
+    #[knuffel(property)]
+    pub id: Option<String>,
+    #[knuffel(property)]
+    pub layer: Option<String>,
+    #[knuffel(property, default)]
+    pub z: i32,
+    #[knuffel(child)]
+    pub frame: Frame,
+    #[knuffel(child, unwrap(argument, str), default)]
+    pub error_correction: ErrorCorrection,
+    #[knuffel(child, unwrap(argument), default=4)]
+    pub quiet_zone: usize,
+    #[knuffel(child, unwrap(argument, str))]
+    pub foreground: Option<ColorRef>,
+    #[knuffel(child, unwrap(argument, str))]
+    pub background: Option<ColorRef>,
+    #[knuffel(children(name="only-if"))]
+    pub conditions: Vec<OnlyIf>,
+}
+
+impl QrCode {
+    // The code for this card, or `None` if its contents are empty
+    pub fn symbol(&self, ctx: &TemplateContext) -> Result<Option<Symbol>, miette::Error> {
+        let contents = self.contents.render(ctx)?;
+        if contents.is_empty() {
+            return Ok(None);
+        }
+
+        let code = qrcode::QrCode::with_error_correction_level(contents.as_bytes(), self.error_correction.level())
+            .map_err(|err| CodeError::InvalidQrCode(contents.clone(), err.to_string()))?;
+        Ok(Some(Symbol {
+            width: code.width(),
+            height: code.width(),
+            dark: code.to_colors().into_iter().map(|color| color == qrcode::Color::Dark).collect(),
+        }))
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ErrorCorrection {
+    Low,
+    Medium,
+    Quartile,
+    High,
+}
+
+impl ErrorCorrection {
+    fn level(&self) -> EcLevel {
+        match self {
+            ErrorCorrection::Low => EcLevel::L,
+            ErrorCorrection::Medium => EcLevel::M,
+            ErrorCorrection::Quartile => EcLevel::Q,
+            ErrorCorrection::High => EcLevel::H,
+        }
+    }
+}
+
+impl Default for ErrorCorrection {
+    fn default() -> Self {
+        ErrorCorrection::Medium
+    }
+}
+
+impl FromStr for ErrorCorrection {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" | "L" => Ok(ErrorCorrection::Low),
+            "medium" | "M" => Ok(ErrorCorrection::Medium),
+            "quartile" | "Q" => Ok(ErrorCorrection::Quartile),
+            "high" | "H" => Ok(ErrorCorrection::High),
+            _ => Err(miette!(r#"Invalid error correction level "{}". Expected one of `"low"`, `"medium"`, `"quartile"`, or `"high"`."#, s)),
+        }
+    }
+}
+
+// A linear barcode, drawn as bars stretched to fill the frame:
+//
+//     barcode "{{ean}}" format="ean13" {
+//         frame anchor="safe-bottom" y=0 w=400 h=150
+//     }
+//
+// `format` is "code128" (the default), which takes any ASCII text, or
+// "ean13", which takes 12 digits, or 13 if the last one is the check digit.
+// The colors and `only-if` rules work the same way as they do for `qrcode`.
+// The quiet zone defaults to 10 modules (the width of the narrowest bar) on
+// either side.
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Barcode {
+    #[knuffel(argument, str)]
+    pub contents: TemplateAwareString,
+    #[knuffel(property)]
+    pub id: Option<String>,
+    #[knuffel(property)]
+    pub layer: Option<String>,
+    #[knuffel(property, default)]
+    pub z: i32,
+    #[knuffel(property, str, default)]
+    pub format: BarcodeFormat,
+    #[knuffel(child)]
+    pub frame: Frame,
+    #[knuffel(child, unwrap(argument), default=10)]
+    pub quiet_zone: usize,
+    #[knuffel(child, unwrap(argument, str))]
+    pub foreground: Option<ColorRef>,
+    #[knuffel(child, unwrap(argument, str))]
+    pub background: Option<ColorRef>,
+    #[knuffel(children(name="only-if"))]
+    pub conditions: Vec<OnlyIf>,
+}
+
+impl Barcode {
+    // The bars for this card, as a symbol one module tall, or `None` if its
+    // contents are empty
+    pub fn symbol(&self, ctx: &TemplateContext) -> Result<Option<Symbol>, miette::Error> {
+        let contents = self.contents.render(ctx)?;
+        if contents.is_empty() {
+            return Ok(None);
+        }
+
+        let invalid = |reason: String| CodeError::InvalidBarcode(self.format, contents.clone(), reason);
+        let bars = match self.format {
+            BarcodeFormat::Code128 => {
+                if !contents.is_ascii() {
+                    return Err(invalid("only ASCII text can be encoded".to_string()).into());
+                }
+                // Code 128 switches between character sets with special
+                // characters, and has to start with one. Set B covers all
+                // printable ASCII.
+                Code128::new(format!("\u{0181}{}", contents))
+                    .map_err(|err| invalid(err.to_string()))?
+                    .encode()
+            },
+            BarcodeFormat::Ean13 => {
+                let digits = ean13_digits(&contents).map_err(invalid)?;
+                EAN13::new(digits)
+                    .map_err(|err| invalid(err.to_string()))?
+                    .encode()
+            },
+        };
+        Ok(Some(Symbol {
+            width: bars.len(),
+            height: 1,
+            dark: bars.into_iter().map(|bar| bar == 1).collect(),
+        }))
+    }
+}
+
+// The 12 digits of an EAN-13 code, with the check digit (if there is one)
+// verified and removed
+fn ean13_digits(contents: &str) -> Result<&str, String> {
+    if !contents.chars().all(|c| c.is_ascii_digit()) {
+        return Err("only digits can be encoded".to_string());
+    }
+    match contents.len() {
+        12 => Ok(contents),
+        13 => {
+            let (digits, check) = contents.split_at(12);
+            let weighted_sum: u32 = digits.bytes()
+                .enumerate()
+                .map(|(idx, digit)| (digit - b'0') as u32 * if idx % 2 == 0 { 1 } else { 3 })
+                .sum();
+            let expected = (10 - weighted_sum % 10) % 10;
+            if check.parse::<u32>() == Ok(expected) {
+                Ok(digits)
+            } else {
+                Err(format!("the check digit should be {}", expected))
+            }
+        },
+        len => Err(format!("expected 12 or 13 digits, found {}", len)),
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum BarcodeFormat {
+    Code128,
+    Ean13,
+}
+
+impl Default for BarcodeFormat {
+    fn default() -> Self {
+        BarcodeFormat::Code128
+    }
+}
+
+impl FromStr for BarcodeFormat {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "code128" => Ok(BarcodeFormat::Code128),
+            "ean13" => Ok(BarcodeFormat::Ean13),
+            _ => Err(miette!(r#"Invalid barcode format "{}". Expected `"code128"` or `"ean13"`."#, s)),
+        }
+    }
+}
+
+impl std::fmt::Display for BarcodeFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BarcodeFormat::Code128 => write!(f, "code128"),
+            BarcodeFormat::Ean13 => write!(f, "ean13"),
+        }
+    }
+}
+
+// The modules (squares or bars) of an encoded code, row by row
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Symbol {
+    pub width: usize,
+    pub height: usize,
+    dark: Vec<bool>,
+}
+
+impl Symbol {
+    // Each horizontal run of dark modules, as (column, row, length), so that
+    // neighboring modules can be drawn as one shape
+    pub fn dark_runs(&self) -> Vec<(usize, usize, usize)> {
+        let mut runs = vec![];
+        for (row, modules) in self.dark.chunks(self.width).enumerate() {
+            let mut start = None;
+            for (col, &dark) in modules.iter().chain(std::iter::once(&false)).enumerate() {
+                match (dark, start) {
+                    (true, None) => start = Some(col),
+                    (false, Some(run_start)) => {
+                        runs.push((run_start, row, col - run_start));
+                        start = None;
+                    },
+                    _ => {},
+                }
+            }
+        }
+        runs
+    }
+}
+
+#[derive(Error, Diagnostic, Debug)]
+pub enum CodeError {
+    #[error("could not encode \"{0}\" as a QR code: {1}")]
+    InvalidQrCode(String, String),
+    #[error("could not encode \"{1}\" as a {0} barcode: {2}")]
+    InvalidBarcode(BarcodeFormat, String, String),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use handlebars::JsonValue as Json;
+
+    use crate::layout::{model::{elements::Element, testing::{card_data, parse_elements}}, templates::{new_registry, TemplateContext}};
+
+    use super::ErrorCorrection;
+
+    #[test]
+    fn it_encodes_qr_codes_and_barcodes() -> miette::Result<()> {
+        let elements = parse_elements(r#"
+            qrcode "{{url}}" {
+                frame w=100 h=100
+                error-correction "high"
+            }
+            barcode "{{ean}}" format="ean13" {
+                frame w=300 h=100
+                foreground "rgb(0, 0, 80)"
+            }
+            barcode "{{ean}}1" format="ean13" { frame w=300 h=100; }
+            barcode "{{missing}}" { frame w=300 h=100; }
+        "#)?;
+
+        let registry = new_registry();
+        let data = card_data(HashMap::from([
+            ("url", Json::from("https://example.com/rules")),
+            ("ean", Json::from("4006381333931")),
+        ]))?;
+        let ctx = TemplateContext::new(&registry, &data);
+
+        match &elements[..] {
+            [Element::Qrcode(qr), Element::Barcode(ean), Element::Barcode(bad_ean), Element::Barcode(empty)] => {
+                assert_eq!(qr.error_correction, ErrorCorrection::High);
+                let symbol = qr.symbol(&ctx)?.expect("a QR code");
+                // 25 bytes at the high level need version 4: 21 modules, plus 4
+                // for each version after the first
+                assert_eq!((symbol.width, symbol.height), (33, 33));
+
+                let symbol = ean.symbol(&ctx)?.expect("a barcode");
+                assert_eq!((symbol.width, symbol.height), (95, 1));
+                // Every EAN-13 code starts with a bar, a space, and a bar
+                assert_eq!(&symbol.dark_runs()[0..2], &[(0, 0, 1), (2, 0, 1)]);
+
+                assert!(bad_ean.symbol(&ctx).is_err());
+                assert_eq!(empty.symbol(&ctx)?, None);
+            },
+            other => panic!("unexpected elements {:?}", other),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+use handlebars::JsonValue as Json;
+
+use crate::{config::fields::{FieldDefinition, FieldType}, data::field::FieldValue, layout::{model::{anchor::Anchoring, dimension::{Dimension, DimensionError, Extent}}, templates::{Scope, TemplateAwareString, TemplateContext, TemplateError}}};
+
+use super::{Bounds, Element};
+
+// A group of elements that can be drawn from any layout in the project.
+// Components can be declared in `.layout` files or in `.component` files:
+//
+//     component "cost-badge" w=120 h=120 {
+//         param "value" type="number"
+//         param "color" default="gold"
+//         rectangle x=0 y=0 w=120 h=120 {
+//             solid "{{color}}"
+//         }
+//         text "{{value}}" {
+//             frame x=0 y=30 w=120 h=60
+//             align "center"
+//         }
+//     }
+//
+// Arguments are passed to the component as text, unless their parameter
+// declares a type the same way a `field` in the project config does.
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Component {
+    #[knuffel(argument)]
+    pub name: String,
+    #[knuffel(property)]
+    pub w: Dimension,
+    #[knuffel(property)]
+    pub h: Dimension,
+    #[knuffel(children(name="param"))]
+    pub params: Vec<Param>,
+    #[knuffel(children)]
+    pub contents: Vec<Element>,
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Param {
+    #[knuffel(argument)]
+    pub name: String,
+    #[knuffel(property)]
+    pub default: Option<TemplateAwareString>,
+    #[knuffel(property(name="type"), str)]
+    pub param_type: Option<FieldType>,
+    #[knuffel(property, default=DEFAULT_LIST_DELIMITER.to_string())]
+    pub delimiter: String,
+}
+
+const DEFAULT_LIST_DELIMITER: &str = ",";
+
+impl Param {
+    // Convert an argument to the parameter's declared type, leaving it as
+    // text if there isn't one or it can't be converted
+    fn value(&self, argument: String) -> FieldValue {
+        let field_type = match &self.param_type {
+            Some(field_type) => field_type.clone(),
+            None => return FieldValue::Text(argument),
+        };
+        let definition = FieldDefinition { name: self.name.clone(), field_type, delimiter: self.delimiter.clone() };
+        let value = FieldValue::Text(argument);
+        match definition.convert(&value) {
+            Some(converted) => converted,
+            None => {
+                log::warn!("Parameter \"{}\" should be a {:?}, but its value \"{}\" can't be converted. Leaving it as is.", self.name, definition.field_type, value);
+                value
+            },
+        }
+    }
+}
+
+// Draws a component with its top left corner at (x, y):
+//
+//     use "cost-badge" x=680 y=40 value="{{cost}}"
+//
+// Like a `box`, the component's elements are positioned relative to that
+// corner and clipped to its size, which can be overridden with `w` and `h`.
+// Every other property sets the parameter with the same name.
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Use {
+    #[knuffel(argument)]
+    pub component: String,
+    #[knuffel(property)]
+    pub id: Option<String>,
+    #[knuffel(property)]
+    pub layer: Option<String>,
+    #[knuffel(property, default)]
+    pub z: i32,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub x: Dimension,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub y: Dimension,
+    #[knuffel(property)]
+    pub w: Option<Dimension>,
+    #[knuffel(property)]
+    pub h: Option<Dimension>,
+    #[knuffel(flatten(property))]
+    pub anchoring: Anchoring,
+    #[knuffel(properties)]
+    pub arguments: HashMap<String, TemplateAwareString>,
+}
+
+impl Use {
+    pub fn bounds(&self, component: &Component, ctx: &TemplateContext, extent: Extent) -> Result<Bounds, DimensionError> {
+        Bounds::resolve(
+            &self.x,
+            &self.y,
+            self.w.as_ref().unwrap_or(&component.w),
+            self.h.as_ref().unwrap_or(&component.h),
+            &self.anchoring,
+            ctx,
+            extent,
+        )
+    }
+
+    /// The scope for drawing the component's elements: the card's fields,
+    /// with the component's parameters taking precedence over any fields of
+    /// the same name. Arguments are filled in where the component is used,
+    /// and converted to their parameter's type if it declares one.
+    pub fn scope(&self, component: &Component, ctx: &TemplateContext) -> Result<Scope, TemplateError> {
+        let mut values = match ctx.data.data() {
+            Json::Object(fields) => fields.clone(),
+            _ => Default::default(),
+        };
+        for param in &component.params {
+            let value = match self.arguments.get(&param.name).or(param.default.as_ref()) {
+                Some(argument) => handlebars::to_json(param.value(argument.render(ctx)?)),
+                None => Json::Null,
+            };
+            values.insert(param.name.clone(), value);
+        }
+        Ok(Scope::new(Json::Object(values)))
+    }
+
+    /// Arguments that don't match any of the component's parameters
+    pub fn unknown_arguments<'u>(&'u self, component: &Component) -> Vec<&'u str> {
+        let mut unknown: Vec<&str> = self.arguments.keys()
+            .filter(|name| !component.params.iter().any(|param| &param.name == *name))
+            .map(|name| name.as_str())
+            .collect();
+        unknown.sort();
+        unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::layout::{model::{geometry::{Geometry, Insets}, testing::card_data}, templates::new_registry};
+
+    use super::*;
+
+    #[derive(knuffel::Decode)]
+    struct Document {
+        #[knuffel(child)]
+        component: Component,
+        #[knuffel(children(name="use"))]
+        uses: Vec<Use>,
+    }
+
+    #[test]
+    fn it_fills_in_component_parameters() -> miette::Result<()> {
+        let document: Document = knuffel::parse("test.layout", r#"
+            component "cost-badge" w=120 h=100 {
+                param "value" type="number"
+                param "color" default="gold"
+                param "label"
+                text "{{value}}" {
+                    frame x=0 y=0 w=120 h=100
+                }
+            }
+            use "cost-badge" x=10 y="{{cost}} * 2" value="{{cost}}" size=3
+            use "cost-badge" x=0 y=0 w="40%" value=7 color="{{name}}" label="1.50"
+        "#)?;
+
+        let registry = new_registry();
+        let data = card_data(HashMap::from([("cost", Json::from(4)), ("name", Json::from("Ogre")), ("color", Json::from("red"))]))?;
+        let ctx = TemplateContext::new(&registry, &data);
+        let component = &document.component;
+        match document.uses.as_slice() {
+            [first, second] => {
+                let parent = Geometry { width: 125, height: 200, cut: Insets::uniform(0), safe: Insets::uniform(0), dpi: 300 }.extent();
+                assert_eq!(first.bounds(component, &ctx, parent)?, Bounds { x: 10.0, y: 8.0, w: 120.0, h: 100.0 });
+                assert_eq!(second.bounds(component, &ctx, parent)?, Bounds { x: 0.0, y: 0.0, w: 50.0, h: 100.0 });
+                assert_eq!(first.unknown_arguments(component), vec!["size"]);
+
+                let scope = first.scope(component, &ctx)?;
+                assert_eq!(scope.this.get("value"), Some(&Json::from(4)));
+                assert_eq!(scope.this.get("color"), Some(&Json::from("gold")));
+                assert_eq!(scope.this.get("label"), Some(&Json::Null));
+                assert_eq!(scope.this.get("name"), Some(&Json::from("Ogre")));
+
+                let scope = second.scope(component, &ctx)?;
+                assert_eq!(scope.this.get("value"), Some(&Json::from(7)));
+                assert_eq!(scope.this.get("color"), Some(&Json::from("Ogre")));
+                assert_eq!(scope.this.get("label"), Some(&Json::from("1.50")));
+            },
+            other => panic!("unexpected uses {:?}", other),
+        }
+
+        Ok(())
+    }
+}
@@ -1,15 +1,830 @@
-use super::Element;
+use std::{fmt::Display, str::FromStr};
 
-#[derive(knuffel::Decode, PartialEq, Eq, Debug)]
+use handlebars::JsonValue as Json;
+use knuffel::{ast::{Literal, TypeName}, decode::{Context, Kind}, errors::DecodeError, span::Spanned, traits::ErrorSpan};
+use miette::miette;
+
+use crate::layout::{model::{anchor::Anchoring, dimension::{Dimension, DimensionError, Extent}, styles::only_if::{self, OnlyIf, OnlyIfOperator}}, templates::{Scope, TemplateAwareString, TemplateContext}};
+
+use super::{Bounds, Element};
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
 pub struct Box {
     #[knuffel(property)]
-    pub x: usize,
+    pub id: Option<String>,
     #[knuffel(property)]
-    pub y: usize,
+    pub layer: Option<String>,
+    #[knuffel(property, default)]
+    pub z: i32,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub x: Dimension,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub y: Dimension,
     #[knuffel(property)]
-    pub w: usize,
+    pub w: Dimension,
     #[knuffel(property)]
-    pub h: usize,
+    pub h: Dimension,
+    #[knuffel(flatten(property))]
+    pub anchoring: Anchoring,
     #[knuffel(children)]
     pub contents: Vec<Element>,
 }
+
+impl Box {
+    pub fn bounds(&self, ctx: &TemplateContext, extent: Extent) -> Result<Bounds, DimensionError> {
+        Bounds::resolve(&self.x, &self.y, &self.w, &self.h, &self.anchoring, ctx, extent)
+    }
+}
+
+// Draws the contents of the first case whose condition holds:
+//
+//     when {
+//         case "{{type}}" "=" "Creature" { ... }
+//         case {
+//             only-if { any { ... } }
+//             ...
+//         }
+//         otherwise { ... }
+//     }
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct When {
+    #[knuffel(property)]
+    pub id: Option<String>,
+    #[knuffel(property)]
+    pub layer: Option<String>,
+    #[knuffel(property, default)]
+    pub z: i32,
+    #[knuffel(children(name="case"))]
+    pub cases: Vec<WhenCase>,
+    #[knuffel(child)]
+    pub otherwise: Option<Otherwise>,
+}
+
+impl When {
+    pub fn select(&self, ctx: &TemplateContext) -> Result<Option<&Vec<Element>>, miette::Error> {
+        for case in &self.cases {
+            if case.matches(ctx)? {
+                return Ok(Some(&case.contents));
+            }
+        }
+        Ok(self.otherwise.as_ref().map(|otherwise| &otherwise.contents))
+    }
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct WhenCase {
+    #[knuffel(argument, str)]
+    pub left: Option<TemplateAwareString>,
+    #[knuffel(argument, str)]
+    pub op: Option<OnlyIfOperator>,
+    #[knuffel(arguments, str)]
+    pub right: Vec<TemplateAwareString>,
+    #[knuffel(child)]
+    pub only_if: Option<OnlyIf>,
+    #[knuffel(children)]
+    pub contents: Vec<Element>,
+}
+
+impl WhenCase {
+    fn matches(&self, ctx: &TemplateContext) -> Result<bool, miette::Error> {
+        if let Some(ref left) = self.left {
+            if !only_if::compare(left, self.op.as_ref(), &self.right, ctx)? {
+                return Ok(false);
+            }
+        }
+        match self.only_if {
+            Some(ref only_if) => only_if.evaluate(ctx),
+            None => Ok(true),
+        }
+    }
+}
+
+// Draws the contents of the first case that lists the value:
+//
+//     switch "{{rarity}}" {
+//         case "common" "uncommon" { ... }
+//         case "rare" { ... }
+//         otherwise { ... }
+//     }
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Switch {
+    #[knuffel(argument, str)]
+    pub value: TemplateAwareString,
+    #[knuffel(property)]
+    pub id: Option<String>,
+    #[knuffel(property)]
+    pub layer: Option<String>,
+    #[knuffel(property, default)]
+    pub z: i32,
+    #[knuffel(children(name="case"))]
+    pub cases: Vec<SwitchCase>,
+    #[knuffel(child)]
+    pub otherwise: Option<Otherwise>,
+}
+
+impl Switch {
+    pub fn select(&self, ctx: &TemplateContext) -> Result<Option<&Vec<Element>>, miette::Error> {
+        let value = self.value.render(ctx)?;
+        for case in &self.cases {
+            for case_value in &case.values {
+                if case_value.render(ctx)? == value {
+                    return Ok(Some(&case.contents));
+                }
+            }
+        }
+        Ok(self.otherwise.as_ref().map(|otherwise| &otherwise.contents))
+    }
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct SwitchCase {
+    #[knuffel(arguments, str)]
+    pub values: Vec<TemplateAwareString>,
+    #[knuffel(children)]
+    pub contents: Vec<Element>,
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Otherwise {
+    #[knuffel(children)]
+    pub contents: Vec<Element>,
+}
+
+// Draws its contents once per item, moving each copy over by dx and down by
+// dy from the one before it:
+//
+//     repeat count="{{cost}}" dx=36 {
+//         image "pip" { frame x=10 y=10 w=32 h=32; scale "fit" }
+//     }
+//     repeat items="keywords" delimiter=";" dx=40 dy=40 columns=4 {
+//         image "keyword-{{this}}" { frame x=10 y=500 w=36 h=36; scale "fit" }
+//     }
+//
+// With `items`, the copies iterate over the named card field: a list field's
+// items, or the parts of a text field split on the delimiter. The name is
+// looked up the same way as in a template, so inside a component or another
+// repeat it can name a parameter, `this`, or `../field`. With `count`,
+// they iterate over the numbers 0 to count - 1. When `columns` is set, copies
+// wrap into a grid with that many columns, each row dy below the last.
+// dx and dy may be negative, to lay copies out leftwards or upwards. No more
+// than 1000 copies are drawn.
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Repeat {
+    #[knuffel(property)]
+    pub id: Option<String>,
+    #[knuffel(property)]
+    pub layer: Option<String>,
+    #[knuffel(property, default)]
+    pub z: i32,
+    #[knuffel(property)]
+    pub count: Option<Dimension>,
+    #[knuffel(property)]
+    pub items: Option<String>,
+    #[knuffel(property, default=DEFAULT_ITEM_DELIMITER.to_string())]
+    pub delimiter: String,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub dx: Dimension,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub dy: Dimension,
+    #[knuffel(property)]
+    pub columns: Option<usize>,
+    #[knuffel(children)]
+    pub contents: Vec<Element>,
+}
+
+const DEFAULT_ITEM_DELIMITER: &str = ",";
+const MAX_REPEAT_COPIES: usize = 1000;
+
+pub struct RepeatCopy {
+    pub scope: Scope,
+    pub offset: (f32, f32),
+}
+
+impl Repeat {
+    pub fn copies(&self, ctx: &TemplateContext, extent: Extent) -> Result<Vec<RepeatCopy>, miette::Error> {
+        let mut items: Vec<Json> = match (&self.items, &self.count) {
+            (Some(field_name), _) => self.field_items(field_name, ctx),
+            (None, Some(count)) => {
+                let count = count.resolve_count(ctx)?;
+                (0..count.min(MAX_REPEAT_COPIES + 1)).map(Json::from).collect()
+            },
+            (None, None) => return Err(miette!("A repeat element needs either a count or a field to take items from")),
+        };
+        if items.len() > MAX_REPEAT_COPIES {
+            log::warn!("A repeat element has more than {} copies; only drawing the first {}", MAX_REPEAT_COPIES, MAX_REPEAT_COPIES);
+            items.truncate(MAX_REPEAT_COPIES);
+        }
+        let dx = self.dx.resolve_signed(ctx, extent.horizontal())?;
+        let dy = self.dy.resolve_signed(ctx, extent.vertical())?;
+        let count = items.len();
+
+        Ok(items.into_iter().enumerate().map(|(index, this)| {
+            let (col, row) = match self.columns {
+                Some(columns) if columns > 0 => (index % columns, index / columns),
+                _ => (index, index),
+            };
+            let offset = (col as f32 * dx, row as f32 * dy);
+            RepeatCopy { scope: Scope::loop_item(this, index, count), offset }
+        }).collect())
+    }
+
+    fn field_items(&self, field_name: &str, ctx: &TemplateContext) -> Vec<Json> {
+        match ctx.lookup(field_name) {
+            Some(Json::Array(items)) => items.clone(),
+            Some(Json::String(text)) => text
+                .split(self.delimiter.as_str())
+                .map(|item| item.trim())
+                .filter(|item| !item.is_empty())
+                .map(Json::from)
+                .collect(),
+            Some(Json::Null) | None => vec![],
+            Some(other) => vec![other.clone()],
+        }
+    }
+}
+
+// Lays its children out one after another, left to right for a `row` or top
+// to bottom for a `column`, instead of at the positions in their frames:
+//
+//     column x=75 y=640 w=675 h=400 gap=12 justify="start" align="stretch" {
+//         text "{{rules}}" { frame x=0 y=0 w=675 h=0 }
+//         text "{{flavor}}" {
+//             frame x=0 y=0 w=675 h=0
+//             only-if "{{flavor}}"
+//         }
+//     }
+//
+// Text takes up as much room along the stack as its laid-out lines need, and
+// everything else takes up the size of its frame. Children hidden by an
+// only-if rule take up no room at all. The chosen branches of `when` and
+// `switch` elements are laid out as if they were children of the stack.
+// Backgrounds and repeats aren't part of the flow, and are drawn relative to
+// the stack's frame.
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Stack {
+    #[knuffel(property)]
+    pub id: Option<String>,
+    #[knuffel(property)]
+    pub layer: Option<String>,
+    #[knuffel(property, default)]
+    pub z: i32,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub x: Dimension,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub y: Dimension,
+    #[knuffel(property)]
+    pub w: Dimension,
+    #[knuffel(property)]
+    pub h: Dimension,
+    #[knuffel(flatten(property))]
+    pub anchoring: Anchoring,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub gap: Dimension,
+    #[knuffel(property, str, default)]
+    pub align: StackAlignment,
+    #[knuffel(property, str, default)]
+    pub justify: StackDistribution,
+    #[knuffel(children)]
+    pub contents: Vec<Element>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+impl Stack {
+    pub fn bounds(&self, ctx: &TemplateContext, extent: Extent) -> Result<Bounds, DimensionError> {
+        Bounds::resolve(&self.x, &self.y, &self.w, &self.h, &self.anchoring, ctx, extent)
+    }
+
+    /// The elements to lay out, with `when` and `switch` elements replaced by
+    /// the contents of whichever branch applies.
+    pub fn children(&self, ctx: &TemplateContext) -> Result<Vec<&Element>, miette::Error> {
+        let mut children = vec![];
+        select_branches(&self.contents, ctx, &mut children)?;
+        Ok(children)
+    }
+
+    /// Position children with the given (width, height) sizes along `axis`
+    /// inside of `bounds`.
+    pub fn arrange(&self, axis: Axis, bounds: &Bounds, gap: f32, sizes: &[(f32, f32)]) -> Vec<Bounds> {
+        let (main_length, cross_length) = match axis {
+            Axis::Horizontal => (bounds.w, bounds.h),
+            Axis::Vertical => (bounds.h, bounds.w),
+        };
+        let count = sizes.len();
+        let used = sizes.iter().map(|&(w, h)| if axis == Axis::Horizontal { w } else { h }).sum::<f32>()
+            + gap * count.saturating_sub(1) as f32;
+        let free = (main_length - used).max(0.0);
+
+        let (start, extra_gap) = match self.justify {
+            StackDistribution::Start => (0f32, 0f32),
+            StackDistribution::Center => (free / 2., 0f32),
+            StackDistribution::End => (free, 0f32),
+            StackDistribution::SpaceBetween if count > 1 => (0f32, free / ((count - 1) as f32)),
+            StackDistribution::SpaceBetween => (0f32, 0f32),
+            StackDistribution::SpaceAround => (free / (2 * count.max(1)) as f32, free / (count.max(1) as f32)),
+            StackDistribution::SpaceEvenly => (free / ((count + 1) as f32), free / ((count + 1) as f32)),
+        };
+
+        let mut position = start;
+        sizes.iter().map(|&(w, h)| {
+            let (main_size, cross_size) = if axis == Axis::Horizontal { (w, h) } else { (h, w) };
+            let cross_size = if self.align == StackAlignment::Stretch { cross_length } else { cross_size };
+            let cross_position = match self.align {
+                StackAlignment::Start | StackAlignment::Stretch => 0f32,
+                StackAlignment::Center => (cross_length - cross_size).max(0.0) / 2.,
+                StackAlignment::End => (cross_length - cross_size).max(0.0),
+            };
+            let main_position = position;
+            position += main_size + gap + extra_gap;
+
+            match axis {
+                Axis::Horizontal => Bounds { x: bounds.x + main_position, y: bounds.y + cross_position, w: main_size, h: cross_size },
+                Axis::Vertical => Bounds { x: bounds.x + cross_position, y: bounds.y + main_position, w: cross_size, h: main_size },
+            }
+        }).collect()
+    }
+}
+
+// Collect elements, replacing `when` and `switch` elements with the contents of
+// the branch that applies
+fn select_branches<'e>(elements: &'e [Element], ctx: &TemplateContext, children: &mut Vec<&'e Element>) -> Result<(), miette::Error> {
+    for element in elements {
+        let selected = match element {
+            Element::When(when) => when.select(ctx)?,
+            Element::Switch(switch) => switch.select(ctx)?,
+            _ => {
+                children.push(element);
+                continue;
+            },
+        };
+        if let Some(contents) = selected {
+            select_branches(contents, ctx, children)?;
+        }
+    }
+    Ok(())
+}
+
+// Where children sit across the stack: at its top (or left) edge, in the
+// middle, at the bottom (or right) edge, or stretched to fill it
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum StackAlignment {
+    Start,
+    Center,
+    End,
+    Stretch,
+}
+
+impl Default for StackAlignment {
+    fn default() -> Self {
+        StackAlignment::Stretch
+    }
+}
+
+impl FromStr for StackAlignment {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "start" => Ok(StackAlignment::Start),
+            "center" => Ok(StackAlignment::Center),
+            "end" => Ok(StackAlignment::End),
+            "stretch" => Ok(StackAlignment::Stretch),
+            _ => Err(miette!(r#"Invalid stack alignment. Expected one of `"start"`, `"center"`, `"end"`, or `"stretch"`."#)),
+        }
+    }
+}
+
+// How any room left over along the stack is handed out
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum StackDistribution {
+    Start,
+    Center,
+    End,
+    SpaceBetween,
+    SpaceAround,
+    SpaceEvenly,
+}
+
+impl Default for StackDistribution {
+    fn default() -> Self {
+        StackDistribution::Start
+    }
+}
+
+impl FromStr for StackDistribution {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "start" => Ok(StackDistribution::Start),
+            "center" => Ok(StackDistribution::Center),
+            "end" => Ok(StackDistribution::End),
+            "space-between" => Ok(StackDistribution::SpaceBetween),
+            "space-around" => Ok(StackDistribution::SpaceAround),
+            "space-evenly" => Ok(StackDistribution::SpaceEvenly),
+            _ => Err(miette!(r#"Invalid stack distribution. Expected one of `"start"`, `"center"`, `"end"`, `"space-between"`, `"space-around"`, or `"space-evenly"`."#)),
+        }
+    }
+}
+
+// Divides its frame into rows and columns of equally-sized cells:
+//
+//     grid x=75 y=700 w=675 h=300 rows=3 cols=3 gap=10 {
+//         cell row=0 col=1 {
+//             text "{{attack}}" { frame x=0 y=0 w=218 h=93 }
+//         }
+//         image "shield" { frame x=10 y=10 w=60 h=60; scale "fit" }
+//     }
+//
+// `cell` children are drawn in the cell at the given row and column, counting
+// from 0. Every other child takes the next cell that isn't already claimed by
+// a `cell`, going across each row from the top. Either way, a cell acts like a
+// `box`: its contents are positioned relative to the cell and clipped to it.
+// Backgrounds and repeats don't take up a cell, and are drawn relative to the
+// grid's frame underneath the cells. A percentage `gap` is relative to the
+// grid's width. `rows` and `cols` must be at least 1.
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Grid {
+    #[knuffel(property)]
+    pub id: Option<String>,
+    #[knuffel(property)]
+    pub layer: Option<String>,
+    #[knuffel(property, default)]
+    pub z: i32,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub x: Dimension,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub y: Dimension,
+    #[knuffel(property)]
+    pub w: Dimension,
+    #[knuffel(property)]
+    pub h: Dimension,
+    #[knuffel(flatten(property))]
+    pub anchoring: Anchoring,
+    #[knuffel(property)]
+    pub rows: CellCount,
+    #[knuffel(property)]
+    pub cols: CellCount,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub gap: Dimension,
+    #[knuffel(children(name="cell"))]
+    pub cells: Vec<Cell>,
+    #[knuffel(children)]
+    pub contents: Vec<Element>,
+}
+
+// The number of rows or columns in a grid, which can't be zero
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct CellCount(usize);
+
+impl CellCount {
+    pub fn get(self) -> usize {
+        self.0
+    }
+}
+
+impl<S> knuffel::DecodeScalar<S> for CellCount where S: ErrorSpan {
+    fn type_check(type_name: &Option<Spanned<TypeName, S>>, ctx: &mut Context<S>) {
+        if let Some(type_name) = type_name {
+            ctx.emit_error(DecodeError::unsupported(type_name, "type annotations are not supported on grid sizes"));
+        }
+    }
+
+    fn raw_decode(literal: &Spanned<Literal, S>, _ctx: &mut Context<S>) -> Result<Self, DecodeError<S>> {
+        match **literal {
+            Literal::Int(ref raw_integer) => match raw_integer.try_into() {
+                Ok(0) => Err(DecodeError::conversion(literal, "a grid needs at least one row and one column")),
+                Ok(count) => Ok(CellCount(count)),
+                Err(err) => Err(DecodeError::conversion(literal, err)),
+            },
+            _ => Err(DecodeError::scalar_kind(Kind::Int, literal)),
+        }
+    }
+}
+
+impl Display for CellCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Cell {
+    #[knuffel(property)]
+    pub row: Dimension,
+    #[knuffel(property)]
+    pub col: Dimension,
+    #[knuffel(children)]
+    pub contents: Vec<Element>,
+}
+
+pub struct GridLayout<'g> {
+    pub cells: Vec<PlacedCell<'g>>,
+    pub overlays: Vec<&'g Element>,
+    // Children that didn't fit in the grid
+    pub overflow: usize,
+}
+
+pub struct PlacedCell<'g> {
+    pub row: usize,
+    pub col: usize,
+    pub contents: Vec<&'g Element>,
+}
+
+impl Grid {
+    pub fn bounds(&self, ctx: &TemplateContext, extent: Extent) -> Result<Bounds, DimensionError> {
+        Bounds::resolve(&self.x, &self.y, &self.w, &self.h, &self.anchoring, ctx, extent)
+    }
+
+    pub fn cell_bounds(&self, grid_bounds: &Bounds, gap: f32, row: usize, col: usize) -> Bounds {
+        let (rows, cols) = (self.rows.get() as f32, self.cols.get() as f32);
+        let cell_w = (grid_bounds.w - gap * (cols - 1.)).max(0.0) / cols;
+        let cell_h = (grid_bounds.h - gap * (rows - 1.)).max(0.0) / rows;
+        Bounds {
+            x: grid_bounds.x + col as f32 * (cell_w + gap),
+            y: grid_bounds.y + row as f32 * (cell_h + gap),
+            w: cell_w,
+            h: cell_h,
+        }
+    }
+
+    pub fn layout(&self, ctx: &TemplateContext) -> Result<GridLayout<'_>, miette::Error> {
+        let mut layout = GridLayout { cells: vec![], overlays: vec![], overflow: 0 };
+        let (rows, cols) = (self.rows.get(), self.cols.get());
+        let mut claimed = vec![false; rows * cols];
+
+        for cell in &self.cells {
+            let (row, col) = (cell.row.resolve_count(ctx)?, cell.col.resolve_count(ctx)?);
+            if row >= rows || col >= cols {
+                layout.overflow += 1;
+                continue;
+            }
+            claimed[row * cols + col] = true;
+            let mut contents = vec![];
+            select_branches(&cell.contents, ctx, &mut contents)?;
+            layout.cells.push(PlacedCell { row, col, contents });
+        }
+
+        let mut children = vec![];
+        select_branches(&self.contents, ctx, &mut children)?;
+        let mut free_cells = (0..claimed.len()).filter(|&idx| !claimed[idx]);
+        for child in children {
+            match child {
+                Element::Background(_) | Element::Repeat(_) => layout.overlays.push(child),
+                _ => match free_cells.next() {
+                    Some(idx) => layout.cells.push(PlacedCell { row: idx / cols, col: idx % cols, contents: vec![child] }),
+                    None => layout.overflow += 1,
+                },
+            }
+        }
+
+        Ok(layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use handlebars::JsonValue as Json;
+    use serde::Serialize;
+
+    use crate::layout::{model::{dimension::Dimension, elements::{shapes::Rectangle, Bounds, Element}, testing::{card, card_data, parse_elements}}, templates::{new_registry, Scope, TemplateAwareString, TemplateContext}};
+
+    use super::{Axis, RepeatCopy};
+
+    #[derive(Serialize)]
+    struct TestCard {
+        rarity: &'static str,
+        power: i64,
+        keywords: &'static str,
+    }
+
+    fn rectangle_x(elements: Option<&Vec<Element>>) -> Option<usize> {
+        match elements.and_then(|elements| elements.first()) {
+            Some(Element::Rectangle(Rectangle { x: Dimension::Fixed(x), .. })) => Some(*x),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn it_selects_conditional_branches() -> miette::Result<()> {
+        let elements = parse_elements(r#"
+            when {
+                case "{{power}}" ">" "10" {
+                    rectangle x=1 y=0 w=1 h=1
+                }
+                case {
+                    only-if {
+                        any {
+                            test "{{rarity}}" "=" "mythic"
+                            test "{{power}}" ">=" "5"
+                        }
+                    }
+                    rectangle x=2 y=0 w=1 h=1
+                }
+                otherwise {
+                    rectangle x=3 y=0 w=1 h=1
+                }
+            }
+            switch "{{rarity}}" {
+                case "common" "uncommon" {
+                    rectangle x=4 y=0 w=1 h=1
+                }
+                case "rare" {
+                    rectangle x=5 y=0 w=1 h=1
+                }
+            }
+        "#)?;
+
+        let registry = new_registry();
+        let data = card_data(TestCard { rarity: "uncommon", power: 7, keywords: "" })?;
+        let ctx = TemplateContext::new(&registry, &data);
+        match elements.as_slice() {
+            [Element::When(when), Element::Switch(switch)] => {
+                assert_eq!(rectangle_x(when.select(&ctx)?), Some(2));
+                assert_eq!(rectangle_x(switch.select(&ctx)?), Some(4));
+            },
+            other => panic!("unexpected elements {:?}", other),
+        }
+
+        let data = card_data(TestCard { rarity: "special", power: 1, keywords: "" })?;
+        let ctx = TemplateContext::new(&registry, &data);
+        match elements.as_slice() {
+            [Element::When(when), Element::Switch(switch)] => {
+                assert_eq!(rectangle_x(when.select(&ctx)?), Some(3));
+                assert_eq!(rectangle_x(switch.select(&ctx)?), None);
+            },
+            other => panic!("unexpected elements {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_lays_out_repeated_copies() -> miette::Result<()> {
+        let elements = parse_elements(r#"
+            repeat count="{{power}} - 4" dx=10 dy=1
+            repeat items="keywords" delimiter=";" dx=30 dy=20 columns=2
+            repeat count=1000000 dx=-2
+        "#)?;
+
+        let registry = new_registry();
+        let data = card_data(TestCard { rarity: "rare", power: 7, keywords: "Flying; Reach;Vigilance" })?;
+        let ctx = TemplateContext::new(&registry, &data);
+        let label = TemplateAwareString::new("{{@index}}:{{this}}:{{../rarity}}{{#if @last}}!{{/if}}".to_string());
+        match elements.as_slice() {
+            [Element::Repeat(by_count), Element::Repeat(by_items), Element::Repeat(too_many)] => {
+                let copies = by_count.copies(&ctx, card())?;
+                assert_eq!(copies.iter().map(|copy| copy.offset).collect::<Vec<_>>(), vec![(0.0, 0.0), (10.0, 1.0), (20.0, 2.0)]);
+
+                let copies = by_items.copies(&ctx, card())?;
+                assert_eq!(copies.iter().map(|copy| copy.offset).collect::<Vec<_>>(), vec![(0.0, 0.0), (30.0, 0.0), (0.0, 20.0)]);
+                let scopes: Vec<_> = copies.into_iter().map(|copy| copy.scope).collect();
+                let labels = (0..scopes.len())
+                    .map(|idx| label.render(&TemplateContext::new(&registry, &data).with_scopes(&scopes[idx..=idx])))
+                    .collect::<Result<Vec<String>, _>>()?;
+                assert_eq!(labels, vec!["0:Flying:rare", "1:Reach:rare", "2:Vigilance:rare!"]);
+
+                let copies = too_many.copies(&ctx, card())?;
+                assert_eq!(copies.len(), 1000);
+                assert_eq!(copies[1].offset, (-2.0, 0.0));
+            },
+            other => panic!("unexpected elements {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_repeats_over_values_in_scope() -> miette::Result<()> {
+        let elements = parse_elements(r#"
+            repeat items="sets" {
+                repeat items="this" dx=10
+                repeat items="../keywords" delimiter=";"
+            }
+            repeat items="pips"
+        "#)?;
+
+        let registry = new_registry();
+        let data = card_data(HashMap::from([
+            ("sets", Json::from(vec![vec!["a", "b"], vec!["c"]])),
+            ("keywords", Json::from("Flying;Reach")),
+        ]))?;
+        let ctx = TemplateContext::new(&registry, &data);
+        let values = |copies: Vec<RepeatCopy>| copies.into_iter().map(|copy| copy.scope.this).collect::<Vec<_>>();
+        match elements.as_slice() {
+            [Element::Repeat(outer), Element::Repeat(in_component)] => {
+                let scopes: Vec<Scope> = outer.copies(&ctx, card())?.into_iter().map(|copy| copy.scope).collect();
+                let inner_ctx = TemplateContext::new(&registry, &data).with_scopes(&scopes[1..=1]);
+                match outer.contents.as_slice() {
+                    [Element::Repeat(by_item), Element::Repeat(by_field)] => {
+                        assert_eq!(values(by_item.copies(&inner_ctx, card())?), vec![Json::from("c")]);
+                        assert_eq!(values(by_field.copies(&inner_ctx, card())?), vec![Json::from("Flying"), Json::from("Reach")]);
+                    },
+                    other => panic!("unexpected elements {:?}", other),
+                }
+
+                // A component's parameters are in the scope its elements are
+                // drawn in
+                let component_scope = [Scope::new(Json::from_iter([("pips".to_string(), Json::from(vec![1, 2, 3]))]))];
+                let component_ctx = TemplateContext::new(&registry, &data).with_scopes(&component_scope);
+                assert_eq!(values(in_component.copies(&component_ctx, card())?), vec![Json::from(1), Json::from(2), Json::from(3)]);
+                assert!(in_component.copies(&ctx, card())?.is_empty());
+            },
+            other => panic!("unexpected elements {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_arranges_stack_children() -> miette::Result<()> {
+        let elements = parse_elements(r#"
+            column x=10 y=20 w=100 h=200 gap=10 {
+                when {
+                    case "{{power}}" ">" "5" {
+                        rectangle x=0 y=0 w=1 h=1
+                    }
+                }
+                rectangle x=0 y=0 w=2 h=2
+            }
+            row x=0 y=0 w=100 h=50 align="center" justify="space-between"
+        "#)?;
+
+        let registry = new_registry();
+        let data = card_data(TestCard { rarity: "rare", power: 7, keywords: "" })?;
+        let ctx = TemplateContext::new(&registry, &data);
+        match elements.as_slice() {
+            [Element::Column(column), Element::Row(row)] => {
+                assert_eq!(column.children(&ctx)?.len(), 2);
+
+                let bounds = column.bounds(&ctx, card())?;
+                assert_eq!(
+                    column.arrange(Axis::Vertical, &bounds, 10.0, &[(30.0, 40.0), (50.0, 60.0)]),
+                    vec![Bounds { x: 10.0, y: 20.0, w: 100.0, h: 40.0 }, Bounds { x: 10.0, y: 70.0, w: 100.0, h: 60.0 }],
+                );
+
+                let bounds = row.bounds(&ctx, card())?;
+                assert_eq!(
+                    row.arrange(Axis::Horizontal, &bounds, 0.0, &[(20.0, 10.0), (20.0, 30.0), (20.0, 50.0)]),
+                    vec![Bounds { x: 0.0, y: 20.0, w: 20.0, h: 10.0 }, Bounds { x: 40.0, y: 10.0, w: 20.0, h: 30.0 }, Bounds { x: 80.0, y: 0.0, w: 20.0, h: 50.0 }],
+                );
+            },
+            other => panic!("unexpected elements {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_places_grid_children_into_cells() -> miette::Result<()> {
+        let elements = parse_elements(r#"
+            grid x=10 y=10 w=320 h=210 rows=2 cols=3 gap=10 {
+                background
+                cell row=0 col=1 {
+                    rectangle x=1 y=0 w=1 h=1
+                }
+                cell row="{{power}}" col=0 {
+                    rectangle x=2 y=0 w=1 h=1
+                }
+                rectangle x=3 y=0 w=1 h=1
+                rectangle x=4 y=0 w=1 h=1
+                rectangle x=5 y=0 w=1 h=1
+                rectangle x=6 y=0 w=1 h=1
+                rectangle x=7 y=0 w=1 h=1
+                rectangle x=8 y=0 w=1 h=1
+            }
+        "#)?;
+
+        let registry = new_registry();
+        let data = card_data(TestCard { rarity: "rare", power: 9, keywords: "" })?;
+        let ctx = TemplateContext::new(&registry, &data);
+        match elements.as_slice() {
+            [Element::Grid(grid)] => {
+                let layout = grid.layout(&ctx)?;
+                let placements: Vec<_> = layout.cells.iter()
+                    .map(|cell| match cell.contents.as_slice() {
+                        [Element::Rectangle(Rectangle { x: Dimension::Fixed(x), .. })] => (cell.row, cell.col, Some(*x)),
+                        _ => (cell.row, cell.col, None),
+                    })
+                    .collect();
+                assert_eq!(placements, vec![(0, 1, Some(1)), (0, 0, Some(3)), (0, 2, Some(4)), (1, 0, Some(5)), (1, 1, Some(6)), (1, 2, Some(7))]);
+                assert_eq!(layout.overlays.len(), 1);
+                assert_eq!(layout.overflow, 2);
+
+                let bounds = grid.bounds(&ctx, card())?;
+                assert_eq!(grid.cell_bounds(&bounds, 10.0, 1, 2), Bounds { x: 230.0, y: 120.0, w: 100.0, h: 100.0 });
+            },
+            other => panic!("unexpected elements {:?}", other),
+        }
+
+        assert!(parse_elements("grid w=10 h=10 rows=0 cols=3").is_err());
+
+        Ok(())
+    }
+}
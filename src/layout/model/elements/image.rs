@@ -7,10 +7,16 @@ use crate::layout::{templates::TemplateAwareString, model::styles::ImageStyle};
 
 use super::Frame;
 
-#[derive(knuffel::Decode, PartialEq, Eq, Debug)]
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
 pub struct Image {
     #[knuffel(argument, str)]
     pub name: TemplateAwareString,
+    #[knuffel(property)]
+    pub id: Option<String>,
+    #[knuffel(property)]
+    pub layer: Option<String>,
+    #[knuffel(property, default)]
+    pub z: i32,
     #[knuffel(child)]
     pub frame: Frame,
     #[knuffel(child, unwrap(argument, str))]
@@ -19,7 +25,7 @@ pub struct Image {
     pub styles: Vec<ImageStyle>,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Scale {
     // Scale the image proportionally so that it's as large as possible while
     // fitting entirely in the frame
@@ -1,9 +1,16 @@
+use crate::layout::templates::TemplateContext;
+
+use super::{anchor::Anchoring, dimension::{Dimension, DimensionError, Extent}};
+
+pub mod codes;
+pub mod component;
 pub mod containers;
 pub mod image;
 pub mod shapes;
+pub mod table;
 pub mod text;
 
-#[derive(knuffel::Decode, PartialEq, Eq, Debug)]
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
 pub enum Element {
     // TODO(#3, #4): triangle, polygon, star, ellipse, circle, path,
     //               group {transform;clip}
@@ -12,24 +19,203 @@ pub enum Element {
     Rectangle(shapes::Rectangle),
     Text(text::Text),
     Image(image::Image),
+    Qrcode(codes::QrCode),
+    Barcode(codes::Barcode),
+    Table(table::Table),
     Box(containers::Box),
+    When(containers::When),
+    Switch(containers::Switch),
+    Repeat(containers::Repeat),
+    Row(containers::Stack),
+    Column(containers::Stack),
+    Grid(containers::Grid),
+    Use(component::Use),
     Background(shapes::Background),
 }
 
-#[derive(knuffel::Decode, PartialEq, Eq, Debug)]
+impl Element {
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            Element::Rectangle(rect) => rect.id.as_deref(),
+            Element::Text(text) => text.id.as_deref(),
+            Element::Image(image) => image.id.as_deref(),
+            Element::Qrcode(qr) => qr.id.as_deref(),
+            Element::Barcode(barcode) => barcode.id.as_deref(),
+            Element::Table(table) => table.id.as_deref(),
+            Element::Box(bx) => bx.id.as_deref(),
+            Element::When(when) => when.id.as_deref(),
+            Element::Switch(switch) => switch.id.as_deref(),
+            Element::Repeat(repeat) => repeat.id.as_deref(),
+            Element::Row(stack) | Element::Column(stack) => stack.id.as_deref(),
+            Element::Grid(grid) => grid.id.as_deref(),
+            Element::Use(usage) => usage.id.as_deref(),
+            Element::Background(background) => background.id.as_deref(),
+        }
+    }
+
+    // The layer this element is drawn in. Elements without one are in the
+    // same layer as the element they're nested in.
+    pub fn layer(&self) -> Option<&str> {
+        match self {
+            Element::Rectangle(rect) => rect.layer.as_deref(),
+            Element::Text(text) => text.layer.as_deref(),
+            Element::Image(image) => image.layer.as_deref(),
+            Element::Qrcode(qr) => qr.layer.as_deref(),
+            Element::Barcode(barcode) => barcode.layer.as_deref(),
+            Element::Table(table) => table.layer.as_deref(),
+            Element::Box(bx) => bx.layer.as_deref(),
+            Element::When(when) => when.layer.as_deref(),
+            Element::Switch(switch) => switch.layer.as_deref(),
+            Element::Repeat(repeat) => repeat.layer.as_deref(),
+            Element::Row(stack) | Element::Column(stack) => stack.layer.as_deref(),
+            Element::Grid(grid) => grid.layer.as_deref(),
+            Element::Use(usage) => usage.layer.as_deref(),
+            Element::Background(background) => background.layer.as_deref(),
+        }
+    }
+
+    pub fn z(&self) -> i32 {
+        match self {
+            Element::Rectangle(rect) => rect.z,
+            Element::Text(text) => text.z,
+            Element::Image(image) => image.z,
+            Element::Qrcode(qr) => qr.z,
+            Element::Barcode(barcode) => barcode.z,
+            Element::Table(table) => table.z,
+            Element::Box(bx) => bx.z,
+            Element::When(when) => when.z,
+            Element::Switch(switch) => switch.z,
+            Element::Repeat(repeat) => repeat.z,
+            Element::Row(stack) | Element::Column(stack) => stack.z,
+            Element::Grid(grid) => grid.z,
+            Element::Use(usage) => usage.z,
+            Element::Background(background) => background.z,
+        }
+    }
+
+    // Every list of elements nested directly inside this one
+    fn children_mut(&mut self) -> Vec<&mut Vec<Element>> {
+        match self {
+            Element::Box(bx) => vec![&mut bx.contents],
+            Element::When(when) =>
+                when.cases.iter_mut()
+                    .map(|case| &mut case.contents)
+                    .chain(when.otherwise.iter_mut().map(|otherwise| &mut otherwise.contents))
+                    .collect(),
+            Element::Switch(switch) =>
+                switch.cases.iter_mut()
+                    .map(|case| &mut case.contents)
+                    .chain(switch.otherwise.iter_mut().map(|otherwise| &mut otherwise.contents))
+                    .collect(),
+            Element::Repeat(repeat) => vec![&mut repeat.contents],
+            Element::Row(stack) | Element::Column(stack) => vec![&mut stack.contents],
+            Element::Grid(grid) =>
+                grid.cells.iter_mut()
+                    .map(|cell| &mut cell.contents)
+                    .chain(std::iter::once(&mut grid.contents))
+                    .collect(),
+            Element::Rectangle(_) | Element::Text(_) | Element::Image(_) | Element::Qrcode(_) | Element::Barcode(_) | Element::Table(_) | Element::Use(_) | Element::Background(_) => vec![],
+        }
+    }
+}
+
+// Put sibling elements in the order they should be drawn: lowest `z` first,
+// and in document order when their `z` values are the same
+pub fn in_draw_order<'e>(elements: impl IntoIterator<Item = &'e Element>) -> Vec<&'e Element> {
+    let mut ordered: Vec<&Element> = elements.into_iter().collect();
+    ordered.sort_by_key(|element| element.z());
+    ordered
+}
+
+// Swap `replacement` in for the element in `elements` (or anywhere beneath
+// it) that has the same id. If there's no such element, or the replacement
+// has no id, hand it back to the caller.
+pub fn replace_by_id(elements: &mut [Element], replacement: Element) -> Option<Element> {
+    let id = match replacement.id() {
+        Some(id) => id.to_string(),
+        None => return Some(replacement),
+    };
+    if let Some(existing) = elements.iter_mut().find(|element| element.id() == Some(id.as_str())) {
+        *existing = replacement;
+        return None;
+    }
+
+    let mut replacement = replacement;
+    for element in elements.iter_mut() {
+        for children in element.children_mut() {
+            match replace_by_id(children, replacement) {
+                None => return None,
+                Some(unplaced) => replacement = unplaced,
+            }
+        }
+    }
+    Some(replacement)
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
 pub struct Frame {
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub x: Dimension,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub y: Dimension,
     #[knuffel(property)]
-    pub x: usize,
-    #[knuffel(property)]
-    pub y: usize,
-    #[knuffel(property)]
-    pub w: usize,
+    pub w: Dimension,
     #[knuffel(property)]
-    pub h: usize,
+    pub h: Dimension,
+    #[knuffel(flatten(property))]
+    pub anchoring: Anchoring,
 }
 
 impl Frame {
-    pub fn center(&self) -> (usize, usize) {
-        (self.x + self.w/2, self.y + self.h/2)
+    pub fn bounds(&self, ctx: &TemplateContext, extent: Extent) -> Result<Bounds, DimensionError> {
+        Bounds::resolve(&self.x, &self.y, &self.w, &self.h, &self.anchoring, ctx, extent)
+    }
+}
+
+// The position and size of an element once its dimensions have been
+// resolved against a card
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Bounds {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Bounds {
+    pub fn resolve(x: &Dimension, y: &Dimension, w: &Dimension, h: &Dimension, anchoring: &Anchoring, ctx: &TemplateContext, extent: Extent) -> Result<Bounds, DimensionError> {
+        anchoring.place(x, y, w, h, ctx, extent)
+    }
+
+    pub fn center(&self) -> (f32, f32) {
+        (self.x + self.w/2.0, self.y + self.h/2.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layout::model::testing::parse_elements;
+
+    use super::in_draw_order;
+
+    #[test]
+    fn it_orders_elements_by_z() -> miette::Result<()> {
+        let elements = parse_elements(r#"
+            rectangle id="frame" layer="cut" z=2 w=10 h=10
+            image "{{art}}" id="art" layer="art" { frame w=10 h=10; scale "fit"; }
+            text "{{name}}" id="name" z=-1 { frame w=10 h=10; }
+            background id="background" z=-1 { solid "white"; }
+        "#)?;
+
+        assert_eq!(
+            in_draw_order(&elements).into_iter().map(|element| element.id()).collect::<Vec<_>>(),
+            vec![Some("name"), Some("background"), Some("art"), Some("frame")],
+        );
+        assert_eq!(
+            elements.iter().map(|element| element.layer()).collect::<Vec<_>>(),
+            vec![Some("cut"), Some("art"), None, None],
+        );
+
+        Ok(())
     }
 }
@@ -1,33 +1,43 @@
-use crate::layout::model::styles::PathStyle;
+use crate::layout::{model::{anchor::Anchoring, dimension::{Dimension, DimensionError, Extent}, styles::PathStyle}, templates::TemplateContext};
 
-#[derive(knuffel::Decode, PartialEq, Eq, Debug)]
+use super::Bounds;
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
 pub struct Rectangle {
     #[knuffel(property)]
-    pub x: usize,
+    pub id: Option<String>,
     #[knuffel(property)]
-    pub y: usize,
+    pub layer: Option<String>,
+    #[knuffel(property, default)]
+    pub z: i32,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub x: Dimension,
+    #[knuffel(property, default=Dimension::Fixed(0))]
+    pub y: Dimension,
     #[knuffel(property)]
-    pub w: usize,
+    pub w: Dimension,
     #[knuffel(property)]
-    pub h: usize,
+    pub h: Dimension,
+    #[knuffel(flatten(property))]
+    pub anchoring: Anchoring,
     #[knuffel(children)]
     pub style: Vec<PathStyle>,
 }
 
-#[derive(knuffel::Decode, PartialEq, Eq, Debug)]
+impl Rectangle {
+    pub fn bounds(&self, ctx: &TemplateContext, extent: Extent) -> Result<Bounds, DimensionError> {
+        Bounds::resolve(&self.x, &self.y, &self.w, &self.h, &self.anchoring, ctx, extent)
+    }
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
 pub struct Background {
+    #[knuffel(property)]
+    pub id: Option<String>,
+    #[knuffel(property)]
+    pub layer: Option<String>,
+    #[knuffel(property, default)]
+    pub z: i32,
     #[knuffel(children)]
     pub style: Vec<PathStyle>,
 }
-
-impl Background {
-    pub fn to_rect(&self, frame_width: usize, frame_height: usize) -> Rectangle {
-        Rectangle {
-            x: 0,
-            y: 0,
-            w: frame_width,
-            h: frame_height,
-            style: self.style.clone(),
-        }
-    }
-}
@@ -0,0 +1,310 @@
+use miette::miette;
+
+use crate::layout::{model::{anchor::Anchoring, dimension::{Dimension, Extent}, styles::{color::ColorRef, only_if::OnlyIf, stroke::Stroke, TextStyle}}, templates::{TemplateAwareString, TemplateContext}};
+
+use super::{text::Text, Bounds, Frame};
+
+// Rows of text laid out in columns. The rows can come from a single field,
+// split into rows and cells:
+//
+//     table "{{equipment}}" row-separator=";" column-separator="|" {
+//         frame x=75 y=650 w=675 h=240
+//         padding 8
+//         column w="15%" { align "center"; }
+//         column
+//         column w=90 { align "right"; }
+//         header "d6" "Item" "Weight" { font weight="bold"; }
+//         stripes "rgba(0, 0, 0, 20)"
+//         border 1 "black"
+//     }
+//
+// or from `row` children, each of which lists its cells:
+//
+//     table {
+//         frame w=400 h=100
+//         row "{{roll_1}}" "{{item_1}}"
+//         row "{{roll_2}}" "{{item_2}}"
+//     }
+//
+// The separators default to ";" and "|". Rows from children come before rows
+// from the field, and rows whose cells are all empty are left out.
+//
+// Columns without a width share whatever width the others leave over, and
+// there are as many columns as the longest row needs. Each cell is drawn as a
+// text element, with the column's styles and then the row's applied on top of
+// the layout's base styles (a row's named `style` replaces its column's).
+// Every row is `row-height` tall, which can't be 0, or an equal share of the
+// frame if it's not given. Rows that don't fit in the frame aren't drawn, and
+// columns that are too wide for it are cut off at its edge.
+//
+// `stripes` fills the rows below the header with its colors in turn; with one
+// color, every other row is filled. `border` strokes every cell, and takes
+// the same arguments as `stroke`.
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Table {
+    #[knuffel(argument, str)]
+    pub source: Option<TemplateAwareString>,
+    #[knuffel(property)]
+    pub id: Option<String>,
+    #[knuffel(property)]
+    pub layer: Option<String>,
+    #[knuffel(property, default)]
+    pub z: i32,
+    #[knuffel(property(name="row-separator"), default=";".to_string())]
+    pub row_separator: String,
+    #[knuffel(property(name="column-separator"), default="|".to_string())]
+    pub column_separator: String,
+    #[knuffel(child)]
+    pub frame: Frame,
+    #[knuffel(child, unwrap(argument))]
+    pub row_height: Option<Dimension>,
+    #[knuffel(child, unwrap(argument), default=Dimension::Fixed(0))]
+    pub padding: Dimension,
+    #[knuffel(children(name="column"))]
+    pub columns: Vec<Column>,
+    #[knuffel(child)]
+    pub header: Option<Row>,
+    #[knuffel(children(name="row"))]
+    pub rows: Vec<Row>,
+    #[knuffel(child)]
+    pub stripes: Option<Stripes>,
+    #[knuffel(child)]
+    pub border: Option<Stroke>,
+    #[knuffel(children(name="only-if"))]
+    pub conditions: Vec<OnlyIf>,
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Column {
+    #[knuffel(property)]
+    pub w: Option<Dimension>,
+    #[knuffel(child, unwrap(argument))]
+    pub style: Option<String>,
+    #[knuffel(children)]
+    pub inline_styles: Vec<TextStyle>,
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Row {
+    #[knuffel(arguments, str)]
+    pub cells: Vec<TemplateAwareString>,
+    #[knuffel(child, unwrap(argument))]
+    pub style: Option<String>,
+    #[knuffel(children)]
+    pub inline_styles: Vec<TextStyle>,
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Stripes {
+    #[knuffel(arguments, str)]
+    pub colors: Vec<ColorRef>,
+}
+
+pub struct TableLayout {
+    pub rows: Vec<PlacedRow>,
+    // Rows that didn't fit in the frame
+    pub overflow: usize,
+}
+
+pub struct PlacedRow {
+    pub bounds: Bounds,
+    pub fill: Option<ColorRef>,
+    pub cells: Vec<PlacedCell>,
+}
+
+pub struct PlacedCell {
+    pub bounds: Bounds,
+    // Where the cell's contents go, inside its padding
+    pub text_bounds: Bounds,
+    pub text: Text,
+}
+
+impl Table {
+    // Place every row and cell of the table within `bounds`, which has the
+    // given extent
+    pub fn layout(&self, ctx: &TemplateContext, bounds: &Bounds, extent: Extent) -> Result<TableLayout, miette::Error> {
+        let mut rows: Vec<(Vec<String>, Option<&Row>)> = vec![];
+        if let Some(ref header) = self.header {
+            rows.push((render_cells(&header.cells, ctx)?, Some(header)));
+        }
+        let header_rows = rows.len();
+        for row in &self.rows {
+            rows.push((render_cells(&row.cells, ctx)?, Some(row)));
+        }
+        if let Some(ref source) = self.source {
+            let source = source.render(ctx)?;
+            for line in source.split(self.row_separator.as_str()) {
+                rows.push((line.split(self.column_separator.as_str()).map(|cell| cell.trim().to_string()).collect(), None));
+            }
+        }
+        let mut row_idx = 0;
+        rows.retain(|(cells, _)| {
+            row_idx += 1;
+            row_idx <= header_rows || cells.iter().any(|cell| !cell.is_empty())
+        });
+
+        let widths = self.column_widths(ctx, bounds.w, extent, rows.iter().map(|(cells, _)| cells.len()).max().unwrap_or(0))?;
+        let (row_height, fitting_rows) = match self.row_height {
+            Some(ref row_height) => match row_height.resolve(ctx, extent.vertical())? {
+                resolved if resolved <= 0.0 => return Err(miette!("A table's row-height must be more than 0, but {} is 0", row_height)),
+                resolved => (resolved, ((bounds.h / resolved) as usize).min(rows.len())),
+            },
+            // Rows split the frame evenly, so they all fit
+            None => (bounds.h / rows.len().max(1) as f32, rows.len()),
+        };
+        let padding = self.padding.resolve(ctx, extent.horizontal())?;
+
+        let mut layout = TableLayout { rows: vec![], overflow: rows.len() - fitting_rows };
+        for (idx, (cells, row)) in rows.into_iter().take(fitting_rows).enumerate() {
+            let row_bounds = Bounds { x: bounds.x, y: bounds.y + idx as f32 * row_height, w: bounds.w, h: row_height };
+            let fill = match (&self.stripes, idx.checked_sub(header_rows)) {
+                (Some(stripes), Some(body_idx)) if !stripes.colors.is_empty() => {
+                    if stripes.colors.len() == 1 {
+                        (body_idx % 2 == 0).then(|| stripes.colors[0].clone())
+                    } else {
+                        Some(stripes.colors[body_idx % stripes.colors.len()].clone())
+                    }
+                },
+                _ => None,
+            };
+
+            let mut placed = PlacedRow { bounds: row_bounds, fill, cells: vec![] };
+            let mut cells = cells.into_iter();
+            let mut x = bounds.x;
+            for (col, &width) in widths.iter().enumerate() {
+                let cell_bounds = Bounds { x, y: row_bounds.y, w: width, h: row_height };
+                x += width;
+                let column = self.columns.get(col);
+                let text_bounds = Bounds {
+                    x: cell_bounds.x + padding,
+                    y: cell_bounds.y + padding,
+                    w: (cell_bounds.w - 2. * padding).max(0.0),
+                    h: (cell_bounds.h - 2. * padding).max(0.0),
+                };
+                let text = Text {
+                    // The cell has already been rendered, so it's used as is
+                    contents: TemplateAwareString::RawString(cells.next().unwrap_or_default()),
+                    id: None,
+                    layer: None,
+                    z: 0,
+                    frame: Frame {
+                        x: Dimension::pixels(text_bounds.x),
+                        y: Dimension::pixels(text_bounds.y),
+                        w: Dimension::pixels(text_bounds.w),
+                        h: Dimension::pixels(text_bounds.h),
+                        anchoring: Anchoring::default(),
+                    },
+                    style: row.and_then(|row| row.style.clone()).or_else(|| column.and_then(|column| column.style.clone())),
+                    inline_styles: column.iter().flat_map(|column| column.inline_styles.iter())
+                        .chain(row.iter().flat_map(|row| row.inline_styles.iter()))
+                        .cloned()
+                        .collect(),
+                };
+                placed.cells.push(PlacedCell { bounds: cell_bounds, text_bounds, text });
+            }
+            layout.rows.push(placed);
+        }
+
+        Ok(layout)
+    }
+
+    fn column_widths(&self, ctx: &TemplateContext, width: f32, extent: Extent, widest_row: usize) -> Result<Vec<f32>, miette::Error> {
+        let column_count = self.columns.len().max(widest_row);
+        let mut widths: Vec<Option<f32>> = vec![None; column_count];
+        for (idx, column) in self.columns.iter().enumerate() {
+            if let Some(ref w) = column.w {
+                widths[idx] = Some(w.resolve(ctx, extent.horizontal())?);
+            }
+        }
+
+        let fixed: f32 = widths.iter().flatten().sum();
+        let flexible = widths.iter().filter(|w| w.is_none()).count();
+        let share = (width - fixed).max(0.0) / flexible.max(1) as f32;
+        Ok(widths.into_iter().map(|w| w.unwrap_or(share)).collect())
+    }
+}
+
+fn render_cells(cells: &[TemplateAwareString], ctx: &TemplateContext) -> Result<Vec<String>, miette::Error> {
+    Ok(cells.iter().map(|cell| cell.render(ctx)).collect::<Result<Vec<_>, _>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use handlebars::JsonValue as Json;
+
+    use crate::layout::{model::{dimension::Dimension, elements::{Bounds, Element}, testing::{card, card_data, parse_elements}}, templates::{new_registry, TemplateContext}};
+
+    use super::Table;
+
+    #[test]
+    fn it_lays_out_tables_from_delimited_data() -> miette::Result<()> {
+        let elements = parse_elements(r#"
+            table "{{equipment}}" {
+                frame w=600 h=200
+                row-height 40
+                padding 5
+                column w="20%" { align "center"; }
+                column
+                column w=100
+                header "d6" "Item" "Qty" { font weight="bold"; }
+                row "{{first_roll}}" "{{first_item}}"
+                row "{{missing}}" ""
+                stripes "rgba(0, 0, 0, 20)"
+                border 1 "black"
+            }
+        "#)?;
+
+        let registry = new_registry();
+        let data = card_data(HashMap::from([
+            ("equipment", Json::from("1|Sword|3; 2 | Shield | 1;;3|Bow|1;4|Rope|2")),
+            ("first_roll", Json::from("0")),
+            ("first_item", Json::from("Stick")),
+        ]))?;
+        let ctx = TemplateContext::new(&registry, &data);
+        let bounds = Bounds { x: 100.0, y: 50.0, w: 600.0, h: 200.0 };
+        let extent = card().within(&bounds);
+
+        let table = match &elements[..] {
+            [Element::Table(table)] => table,
+            other => panic!("unexpected elements {:?}", other),
+        };
+        let layout = table.layout(&ctx, &bounds, extent)?;
+
+        // A header, the "Stick" row, and three of the four rows from the
+        // field fit; the empty rows are dropped
+        assert_eq!(layout.overflow, 1);
+        let contents = layout.rows.iter()
+            .map(|row| row.cells.iter().map(|cell| cell.text.contents.source()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(contents, vec![
+            vec!["d6", "Item", "Qty"],
+            vec!["0", "Stick", ""],
+            vec!["1", "Sword", "3"],
+            vec!["2", "Shield", "1"],
+            vec!["3", "Bow", "1"],
+        ]);
+
+        assert_eq!(
+            layout.rows[2].cells.iter().map(|cell| cell.bounds).collect::<Vec<_>>(),
+            vec![
+                Bounds { x: 100.0, y: 130.0, w: 120.0, h: 40.0 },
+                Bounds { x: 220.0, y: 130.0, w: 380.0, h: 40.0 },
+                Bounds { x: 600.0, y: 130.0, w: 100.0, h: 40.0 },
+            ],
+        );
+        assert_eq!(layout.rows[2].cells[0].text_bounds, Bounds { x: 105.0, y: 135.0, w: 110.0, h: 30.0 });
+        // The header has the column's styles and then its own
+        assert_eq!(layout.rows[0].cells[0].text.inline_styles.len(), 2);
+        assert_eq!(
+            layout.rows.iter().map(|row| row.fill.is_some()).collect::<Vec<_>>(),
+            vec![false, true, false, true, false],
+        );
+
+        let zero_height = Table { row_height: Some(Dimension::Fixed(0)), ..table.clone() };
+        assert!(zero_height.layout(&ctx, &bounds, extent).is_err());
+
+        Ok(())
+    }
+}
@@ -2,10 +2,16 @@ use crate::layout::{model::styles::TextStyle, templates::TemplateAwareString};
 
 use super::Frame;
 
-#[derive(knuffel::Decode, PartialEq, Eq, Debug)]
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
 pub struct Text {
     #[knuffel(argument, str)]
     pub contents: TemplateAwareString,
+    #[knuffel(property)]
+    pub id: Option<String>,
+    #[knuffel(property)]
+    pub layer: Option<String>,
+    #[knuffel(property, default)]
+    pub z: i32,
     #[knuffel(child)]
     pub frame: Frame,
     #[knuffel(child, unwrap(argument))]
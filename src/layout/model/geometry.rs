@@ -1,19 +1,68 @@
-use knuffel::{ast::{Value, Literal}, decode::Kind, span::Spanned};
+use knuffel::{ast::Value, decode::Context, errors::DecodeError, traits::ErrorSpan};
 
-#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+use super::{dimension::{Dimension, Extent, Measure}, elements::Bounds};
+
+// The size of a card, and its cut and safe zones. Lengths may be given in
+// pixels or with physical units, which are converted using `dpi`:
+//
+//     geometry {
+//         width "63mm"
+//         height "88mm"
+//         cut "3mm"
+//         safe "6mm"
+//         dpi 300
+//     }
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Geometry {
-    #[knuffel(child, unwrap(argument))]
     pub width: usize,
-    #[knuffel(child, unwrap(argument))]
     pub height: usize,
-    #[knuffel(child)]
     pub cut: Insets,
-    #[knuffel(child)]
     pub safe: Insets,
-    #[knuffel(child, unwrap(argument), default=300usize)]
     pub dpi: usize,
 }
 
+// Geometry as written, before its lengths have been converted to pixels
+#[derive(knuffel::Decode)]
+struct RawGeometry {
+    #[knuffel(child, unwrap(argument))]
+    width: Dimension,
+    #[knuffel(child, unwrap(argument))]
+    height: Dimension,
+    #[knuffel(child)]
+    cut: RawInsets,
+    #[knuffel(child)]
+    safe: RawInsets,
+    #[knuffel(child, unwrap(argument), default=300usize)]
+    dpi: usize,
+}
+
+impl<S> knuffel::Decode<S> for Geometry where S: ErrorSpan {
+    fn decode_node(node: &knuffel::ast::SpannedNode<S>, ctx: &mut Context<S>) -> Result<Self, DecodeError<S>> {
+        let raw = RawGeometry::decode_node(node, ctx)?;
+        let measure = Measure::absolute(raw.dpi);
+        let to_pixels = |dimension: &Dimension| dimension.to_pixels(measure)
+            .map_err(|err| DecodeError::conversion(node, err.to_string()));
+
+        Ok(Geometry {
+            width: to_pixels(&raw.width)?,
+            height: to_pixels(&raw.height)?,
+            cut: Insets {
+                top: to_pixels(&raw.cut.top)?,
+                right: to_pixels(&raw.cut.right)?,
+                bottom: to_pixels(&raw.cut.bottom)?,
+                left: to_pixels(&raw.cut.left)?,
+            },
+            safe: Insets {
+                top: to_pixels(&raw.safe.top)?,
+                right: to_pixels(&raw.safe.right)?,
+                bottom: to_pixels(&raw.safe.bottom)?,
+                left: to_pixels(&raw.safe.left)?,
+            },
+            dpi: raw.dpi,
+        })
+    }
+}
+
 impl Geometry {
     pub fn content_size(&self) -> (usize, usize) {
         (
@@ -21,6 +70,28 @@ impl Geometry {
             self.height - self.cut.top - self.cut.bottom,
         )
     }
+
+    pub fn zones(&self) -> Zones {
+        Zones {
+            card: Bounds { x: 0.0, y: 0.0, w: self.width as f32, h: self.height as f32 },
+            cut: self.cut.inside(self.width, self.height),
+            safe: self.safe.inside(self.width, self.height),
+        }
+    }
+
+    // The whole card, as somewhere to place elements
+    pub fn extent(&self) -> Extent {
+        Extent { w: self.width as f32, h: self.height as f32, dpi: self.dpi, origin: (0.0, 0.0), zones: self.zones() }
+    }
+}
+
+// The edge of the card, the cut line, and the edge of the safe zone, in
+// pixels from the card's top left corner
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Zones {
+    pub card: Bounds,
+    pub cut: Bounds,
+    pub safe: Bounds,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
@@ -31,42 +102,80 @@ pub struct Insets {
     pub left: usize,
 }
 
-impl<S> knuffel::Decode<S> for Insets where S: knuffel::traits::ErrorSpan {
-    fn decode_node(node: &knuffel::ast::SpannedNode<S>, _ctx: &mut knuffel::decode::Context<S>)
-        -> Result<Self, knuffel::errors::DecodeError<S>> {
+impl Insets {
+    pub fn uniform(size: usize) -> Insets {
+        Insets { top: size, right: size, bottom: size, left: size }
+    }
+
+    // The box left over after insetting a width x height box by these insets
+    pub fn inside(&self, width: usize, height: usize) -> Bounds {
+        Bounds {
+            x: self.left as f32,
+            y: self.top as f32,
+            w: width.saturating_sub(self.left + self.right) as f32,
+            h: height.saturating_sub(self.top + self.bottom) as f32,
+        }
+    }
+}
+
+struct RawInsets {
+    top: Dimension,
+    right: Dimension,
+    bottom: Dimension,
+    left: Dimension,
+}
+
+impl<S> knuffel::Decode<S> for RawInsets where S: ErrorSpan {
+    fn decode_node(node: &knuffel::ast::SpannedNode<S>, ctx: &mut Context<S>)
+        -> Result<Self, DecodeError<S>> {
+        let mut decode = |value: &Value<S>| knuffel::traits::DecodeScalar::decode(value, ctx);
         match node.arguments.as_slice() {
-            [Value { literal, .. }] => Ok(Insets::uniform(extract_integer(literal)?)),
-            [
-                Value { literal: top, .. },
-                Value { literal: right, .. },
-                Value { literal: bottom, .. },
-                Value { literal: left, .. },
-            ] => Ok(Insets {
-                top: extract_integer(top)?,
-                right: extract_integer(right)?,
-                bottom: extract_integer(bottom)?,
-                left: extract_integer(left)?,
+            [size] => {
+                let size: Dimension = decode(size)?;
+                Ok(RawInsets { top: size.clone(), right: size.clone(), bottom: size.clone(), left: size })
+            },
+            [top, right, bottom, left] => Ok(RawInsets {
+                top: decode(top)?,
+                right: decode(right)?,
+                bottom: decode(bottom)?,
+                left: decode(left)?,
             }),
-            _ => Err(knuffel::errors::DecodeError::conversion(node, "Invalid number of arguments for insets. Expected either 1 or 4."))
+            _ => Err(DecodeError::conversion(node, "Invalid number of arguments for insets. Expected either 1 or 4."))
         }
     }
 }
 
-impl Insets {
-    pub fn uniform(size: usize) -> Insets {
-        Insets { top: size, right: size, bottom: size, left: size }
+#[cfg(test)]
+mod tests {
+    use super::{Geometry, Insets};
+
+    #[derive(knuffel::Decode, Debug)]
+    struct Document {
+        #[knuffel(child)]
+        geometry: Geometry,
     }
-}
 
-fn extract_integer<S>(literal: &Spanned<Literal, S>) -> Result<usize, knuffel::errors::DecodeError<S>>
-    where S: knuffel::traits::ErrorSpan {
-    match **literal {
-        Literal::Int(ref raw_integer) =>
-            Ok(
-                raw_integer
-                .try_into()
-                .map_err(|err| knuffel::errors::DecodeError::conversion(&literal, err))?
-            ),
-        _ => Err(knuffel::errors::DecodeError::scalar_kind(Kind::Int, &literal))
+    #[test]
+    fn it_converts_physical_units_in_geometry() -> miette::Result<()> {
+        let document: Document = knuffel::parse("test.layout", r#"
+            geometry {
+                width "63mm"
+                height "3.5in"
+                cut "3mm"
+                safe 36 "6mm" 36.4 "0.5cm"
+                dpi 300
+            }
+        "#)?;
+
+        assert_eq!(document.geometry, Geometry {
+            width: 744,
+            height: 1050,
+            cut: Insets::uniform(35),
+            safe: Insets { top: 36, right: 71, bottom: 36, left: 59 },
+            dpi: 300,
+        });
+        assert!(knuffel::parse::<Document>("test.layout", r#"geometry { width "50%"; height 10; cut 0; safe 0; }"#).is_err());
+
+        Ok(())
     }
 }
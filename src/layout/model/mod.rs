@@ -1,10 +1,14 @@
-use self::{geometry::Geometry, elements::Element};
+use self::{geometry::Geometry, elements::{Element, component::Component}};
 
+pub mod anchor;
+pub mod dimension;
 pub mod elements;
 pub mod geometry;
 pub mod styles;
+#[cfg(test)]
+pub(crate) mod testing;
 
-#[derive(knuffel::Decode, PartialEq, Eq, Debug)]
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
 pub struct Layout {
     #[knuffel(child)]
     pub geometry: Geometry,
@@ -14,7 +18,75 @@ pub struct Layout {
     pub elements: Vec<Element>,
 }
 
+// A `.layout` file as written. It may extend another layout:
+//
+//     extends "default"
+//     base {
+//         text {
+//             font family="Papyrus"
+//         }
+//     }
+//     background id="background" {
+//         solid "white"
+//     }
+//
+// in which case it starts from a copy of that layout. Its geometry (if any)
+// replaces the parent's, its base styles are applied after the parent's, and
+// each of its elements either replaces the parent's element with the same
+// `id` or is added after the parent's elements.
 #[derive(knuffel::Decode, PartialEq, Eq, Debug)]
+pub struct LayoutDefinition {
+    #[knuffel(child, unwrap(argument))]
+    pub extends: Option<String>,
+    #[knuffel(child)]
+    pub geometry: Option<Geometry>,
+    #[knuffel(child)]
+    pub base: Option<BaseStyles>,
+    #[knuffel(children(name="component"))]
+    pub components: Vec<Component>,
+    #[knuffel(children)]
+    pub elements: Vec<Element>,
+}
+
+impl LayoutDefinition {
+    // Build a layout that doesn't extend anything. Fails if there's no
+    // geometry to go on.
+    pub fn into_layout(self) -> Option<Layout> {
+        Some(Layout {
+            geometry: self.geometry?,
+            base: self.base,
+            elements: self.elements,
+        })
+    }
+
+    pub fn extend(self, parent: &Layout) -> Layout {
+        let mut layout = parent.clone();
+        if let Some(geometry) = self.geometry {
+            layout.geometry = geometry;
+        }
+        if let Some(base) = self.base {
+            match layout.base {
+                Some(ref mut parent_base) => parent_base.extend(base),
+                None => layout.base = Some(base),
+            }
+        }
+        for element in self.elements {
+            if let Some(new_element) = elements::replace_by_id(&mut layout.elements, element) {
+                layout.elements.push(new_element);
+            }
+        }
+        layout
+    }
+}
+
+// A `.component` file, which only declares components
+#[derive(knuffel::Decode, PartialEq, Eq, Debug)]
+pub struct ComponentLibrary {
+    #[knuffel(children(name="component"))]
+    pub components: Vec<Component>,
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
 pub struct BaseStyles {
     #[knuffel(child)]
     pub path: Option<base_styles::Path>,
@@ -22,16 +94,29 @@ pub struct BaseStyles {
     pub text: Option<base_styles::Text>,
 }
 
+impl BaseStyles {
+    // Layer `other` on top of these styles. Its styles come last, so they win
+    // wherever the two disagree.
+    pub fn extend(&mut self, other: BaseStyles) {
+        if let Some(path) = other.path {
+            self.path.get_or_insert_with(|| base_styles::Path { styles: vec![] }).styles.extend(path.styles);
+        }
+        if let Some(text) = other.text {
+            self.text.get_or_insert_with(|| base_styles::Text { styles: vec![] }).styles.extend(text.styles);
+        }
+    }
+}
+
 pub mod base_styles {
     use super::styles;
 
-    #[derive(knuffel::Decode, PartialEq, Eq, Debug)]
+    #[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
     pub struct Path {
         #[knuffel(children)]
         pub styles: Vec<styles::PathStyle>,
     }
 
-    #[derive(knuffel::Decode, PartialEq, Eq, Debug)]
+    #[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
     pub struct Text {
         #[knuffel(children)]
         pub styles: Vec<styles::TextStyle>,
@@ -40,9 +125,9 @@ pub mod base_styles {
 
 #[cfg(test)]
 mod tests {
-    use crate::layout::{model::{geometry::{Insets, Geometry}, elements::{shapes::{Background, Rectangle}, Element, text::Text, Frame, containers::Box}, styles::{solid::Solid, PathStyle, only_if::{OnlyIf, OnlyIfOperator}, stroke::{Stroke, DashPattern}, TextStyle, font::{Font, Weight}, color::{ColorRef, Color}, text::{Alignment, Align}}, base_styles, BaseStyles}, templates::TemplateAwareString};
+    use crate::layout::{model::{anchor::Anchoring, dimension::Dimension, geometry::{Insets, Geometry}, elements::{shapes::{Background, Rectangle}, Element, text::Text, Frame, containers::Box}, styles::{solid::Solid, PathStyle, only_if::{OnlyIf, OnlyIfOperator}, stroke::{Stroke, DashPattern}, TextStyle, font::{Font, Weight}, color::{ColorRef, Color}, text::{Alignment, Align}}, base_styles, BaseStyles}, templates::TemplateAwareString};
 
-    use super::Layout;
+    use super::{Layout, LayoutDefinition};
 
     const EXAMPLE_DOCUMENT: &str = r#"
     geometry {
@@ -108,6 +193,9 @@ mod tests {
                 }),
                 elements: vec![
                     Element::Background(Background {
+                        id: None,
+                        layer: None,
+                        z: 0,
                         style: vec![
                             PathStyle::Solid(Solid {
                                 color: ColorRef::Named(TemplateAwareString::new("white".to_string())),
@@ -115,22 +203,31 @@ mod tests {
                         ],
                     }),
                     Element::Rectangle(Rectangle {
-                        x: 1,
-                        y: 2,
-                        w: 3,
-                        h: 4,
+                        id: None,
+                        layer: None,
+                        z: 0,
+                        x: 1.into(),
+                        y: 2.into(),
+                        w: 3.into(),
+                        h: 4.into(),
+                        anchoring: Anchoring::default(),
                         style: vec![],
                     }),
                     Element::Rectangle(Rectangle {
-                        x: 5,
-                        y: 6,
-                        w: 7,
-                        h: 8,
+                        id: None,
+                        layer: None,
+                        z: 0,
+                        x: 5.into(),
+                        y: 6.into(),
+                        w: 7.into(),
+                        h: 8.into(),
+                        anchoring: Anchoring::default(),
                         style: vec![
                             PathStyle::OnlyIf(OnlyIf {
-                                left: TemplateAwareString::new("some text".to_string()),
+                                left: Some(TemplateAwareString::new("some text".to_string())),
                                 op: None,
                                 right: vec![],
+                                conditions: vec![],
                             }),
                             PathStyle::Stroke(Stroke {
                                 width: 3,
@@ -143,37 +240,50 @@ mod tests {
                         ],
                     }),
                     Element::Text(Text {
+                        id: None,
+                        layer: None,
+                        z: 0,
                         contents: TemplateAwareString::new("some text".to_string()),
                         frame: Frame {
-                            x: 100,
-                            y: 200,
-                            w: 300,
-                            h: 400,
+                            x: 100.into(),
+                            y: 200.into(),
+                            w: 300.into(),
+                            h: 400.into(),
+                            anchoring: Anchoring::default(),
                         },
                         style: None,
                         inline_styles: vec![
                             TextStyle::OnlyIf(OnlyIf {
-                                left: TemplateAwareString::new("some {{other}} text".to_string()),
+                                left: Some(TemplateAwareString::new("some {{other}} text".to_string())),
                                 op: Some(OnlyIfOperator::In),
                                 right: vec![
                                     TemplateAwareString::new("xxx".to_string()),
                                     TemplateAwareString::new("yyy".to_string()),
                                     TemplateAwareString::new("zzz".to_string()),
                                 ],
+                                conditions: vec![],
                             }),
                         ],
                     }),
                     Element::Box(Box {
-                        x: 50,
-                        y: 50,
-                        w: 100,
-                        h: 100,
+                        id: None,
+                        layer: None,
+                        z: 0,
+                        x: 50.into(),
+                        y: 50.into(),
+                        w: 100.into(),
+                        h: 100.into(),
+                        anchoring: Anchoring::default(),
                         contents: vec![
                             Element::Rectangle(Rectangle {
-                                x: 1,
-                                y: 2,
-                                w: 3,
-                                h: 4,
+                                id: None,
+                                layer: None,
+                                z: 0,
+                                x: 1.into(),
+                                y: 2.into(),
+                                w: 3.into(),
+                                h: 4.into(),
+                                anchoring: Anchoring::default(),
                                 style: vec![
                                     PathStyle::Stroke(
                                         Stroke {
@@ -185,12 +295,16 @@ mod tests {
                                 ],
                             }),
                             Element::Text(Text {
+                                id: None,
+                                layer: None,
+                                z: 0,
                                 contents: TemplateAwareString::new("some text".to_string()),
                                 frame: Frame {
-                                    x: 10,
-                                    y: 20,
-                                    w: 30,
-                                    h: 40,
+                                    x: 10.into(),
+                                    y: 20.into(),
+                                    w: 30.into(),
+                                    h: 40.into(),
+                                    anchoring: Anchoring::default(),
                                 },
                                 style: Some("rules".to_string()),
                                 inline_styles: vec![
@@ -210,4 +324,45 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn it_extends_a_parent_layout() -> miette::Result<()> {
+        let parent: Layout = knuffel::parse("example.kdl", EXAMPLE_DOCUMENT)?;
+        let child: LayoutDefinition = knuffel::parse("child.kdl", r#"
+        extends "example"
+        base {
+            text {
+                font family="Papyrus"
+            }
+        }
+        rectangle id="inner" x=9 y=9 w=9 h=9
+        rectangle id="extra" x=0 y=0 w=1 h=1
+        "#)?;
+        assert_eq!(child.extends.as_deref(), Some("example"));
+
+        let mut parent = parent;
+        if let Element::Box(ref mut bx) = parent.elements[4] {
+            if let Element::Rectangle(ref mut rect) = bx.contents[0] {
+                rect.id = Some("inner".to_string());
+            }
+        }
+        let layout = child.extend(&parent);
+
+        assert_eq!(layout.geometry, parent.geometry);
+        assert_eq!(
+            layout.base.and_then(|base| base.text).map(|text| text.styles.len()),
+            Some(3),
+        );
+        assert_eq!(layout.elements.len(), parent.elements.len() + 1);
+        assert_eq!(layout.elements.last().and_then(|element| element.id()), Some("extra"));
+        match layout.elements[4] {
+            Element::Box(ref bx) => assert!(matches!(
+                bx.contents[0],
+                Element::Rectangle(Rectangle { x: Dimension::Fixed(9), .. }),
+            )),
+            ref other => panic!("expected a box, found {:?}", other),
+        }
+
+        Ok(())
+    }
 }
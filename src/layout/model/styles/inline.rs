@@ -0,0 +1,91 @@
+use std::str::FromStr;
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+use super::{TextStyle, font::{Font, Weight, Width}, text::{Foreground, Background, Size, Units}, color::ColorParseError};
+
+// Build the text style described by a single markup tag argument or
+// attribute, e.g. the "red" in `<color red>` or the `size=9pt` in
+// `<rules size=9pt>`.
+pub fn style_for_attribute(key: &str, value: &str) -> Result<TextStyle, InlineStyleError> {
+    match key {
+        "color" | "foreground" => Ok(TextStyle::Foreground(Foreground { color: value.parse()? })),
+        "background" => Ok(TextStyle::Background(Background { color: value.parse()? })),
+        "size" => Ok(TextStyle::Size(parse_size(value)?)),
+        "font" | "family" => Ok(TextStyle::Font(Font { family: Some(value.to_string()), weight: None, width: None, style: None })),
+        "weight" => {
+            let weight = parse_font_keyword(value, Weight::Normal).ok_or_else(|| InlineStyleError::InvalidWeight(value.to_string()))?;
+            Ok(TextStyle::Font(Font { family: None, weight: Some(weight), width: None, style: None }))
+        },
+        "width" => {
+            let width = parse_font_keyword(value, Width::Normal).ok_or_else(|| InlineStyleError::InvalidWidth(value.to_string()))?;
+            Ok(TextStyle::Font(Font { family: None, weight: None, width: Some(width), style: None }))
+        },
+        "style" => Ok(TextStyle::Font(Font { family: None, weight: None, width: None, style: Some(value.to_string()) })),
+        _ => Err(InlineStyleError::UnknownAttribute(key.to_string())),
+    }
+}
+
+// Font weights and widths fall back to normal for names they don't know,
+// which in markup is more likely a typo than a request for normal text
+fn parse_font_keyword<T: FromStr + PartialEq>(value: &str, normal: T) -> Option<T> {
+    let parsed = value.parse::<T>().ok()?;
+    let is_normal = value.to_ascii_lowercase().replace(" ", "").replace("-", "") == "normal";
+    (parsed != normal || is_normal).then_some(parsed)
+}
+
+fn parse_size(value: &str) -> Result<Size, InlineStyleError> {
+    let trimmed = value.trim();
+    let digits_end = trimmed.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(trimmed.len());
+    let (number, units) = trimmed.split_at(digits_end);
+    let size = number.parse::<usize>().map_err(|_| InlineStyleError::InvalidSize(value.to_string()))?;
+    let units = match units.trim() {
+        "" | "px" => Units::Pixels,
+        "pt" => Units::Points,
+        _ => return Err(InlineStyleError::InvalidSize(value.to_string())),
+    };
+
+    Ok(Size { size, units })
+}
+
+#[derive(Error, Diagnostic, Debug)]
+pub enum InlineStyleError {
+    #[error("unknown style attribute \"{0}\" (expected one of \"color\", \"background\", \"size\", \"font\", \"weight\", \"width\", or \"style\")")]
+    UnknownAttribute(String),
+    #[error("invalid text size \"{0}\" (expected a whole number, optionally followed by \"px\" or \"pt\")")]
+    InvalidSize(String),
+    #[error("unknown font weight \"{0}\" (expected one of \"thin\", \"extra-light\", \"light\", \"normal\", \"medium\", \"semi-bold\", \"bold\", \"extra-bold\", \"black\", or \"extra-black\")")]
+    InvalidWeight(String),
+    #[error("unknown font width \"{0}\" (expected one of \"ultra-condensed\", \"condensed\", \"semi-condensed\", \"normal\", \"semi-wide\", \"wide\", or \"ultra-wide\")")]
+    InvalidWidth(String),
+    #[error(transparent)]
+    InvalidColor(#[from] ColorParseError),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layout::{model::styles::{TextStyle, text::{Size, Units, Foreground}, color::ColorRef}, templates::TemplateAwareString};
+
+    use super::style_for_attribute;
+
+    #[test]
+    fn it_builds_styles_from_attributes() -> miette::Result<()> {
+        assert_eq!(
+            style_for_attribute("size", "9pt")?,
+            TextStyle::Size(Size { size: 9, units: Units::Points }),
+        );
+        assert_eq!(
+            style_for_attribute("color", "dark gray")?,
+            TextStyle::Foreground(Foreground { color: ColorRef::Named(TemplateAwareString::new("dark gray".to_string())) }),
+        );
+        assert!(style_for_attribute("size", "large").is_err());
+        assert!(style_for_attribute("sparkles", "yes").is_err());
+        assert!(style_for_attribute("weight", "semi-bold").is_ok());
+        assert!(style_for_attribute("weight", "Normal").is_ok());
+        assert!(style_for_attribute("weight", "blod").is_err());
+        assert!(style_for_attribute("width", "narrow").is_err());
+
+        Ok(())
+    }
+}
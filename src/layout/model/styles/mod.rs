@@ -1,5 +1,6 @@
 pub mod color;
 pub mod font;
+pub mod inline;
 pub mod only_if;
 pub mod solid;
 pub mod stroke;
@@ -19,6 +20,11 @@ pub enum TextStyle {
     Align(text::Align),
     Foreground(text::Foreground),
     Background(text::Background),
+    MaxLines(text::MaxLines),
+    Ellipsis(text::Ellipsis),
+    Columns(text::Columns),
+    List(text::List),
+    Hyphenate(text::Hyphenate),
     OnlyIf(only_if::OnlyIf),
 }
 
@@ -1,45 +1,195 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr, sync::Mutex};
 
-use handlebars::Context;
+use knuffel::{decode::Context, errors::DecodeError, traits::ErrorSpan};
+use lazy_static::lazy_static;
 use miette::miette;
+use regex::Regex;
 
-use crate::layout::templates::{TemplateAwareString, TemplateError};
+use crate::layout::templates::{TemplateAwareString, TemplateContext, TemplateError};
 
-#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+// An only-if rule can test a single condition inline:
+//
+//     only-if "{{power}}" ">=" "3"
+//
+// or combine several using child nodes, which must all hold:
+//
+//     only-if {
+//         test "{{type}}" "in" "Creature" "Vehicle"
+//         any {
+//             test "{{power}}" ">" "3"
+//             test "{{keywords}}" "contains" "Flying"
+//         }
+//         not {
+//             test "{{name}}" "matches" "^Token"
+//         }
+//     }
+//
+// A rule with no test and no conditions is an error.
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct OnlyIf {
+    pub left: Option<TemplateAwareString>,
+    pub op: Option<OnlyIfOperator>,
+    pub right: Vec<TemplateAwareString>,
+    pub conditions: Vec<Condition>,
+}
+
+// An only-if rule as written, before checking that it tests something
+#[derive(knuffel::Decode)]
+struct RawOnlyIf {
     #[knuffel(argument, str)]
-    pub left: TemplateAwareString,
+    left: Option<TemplateAwareString>,
     #[knuffel(argument, str)]
-    pub op: Option<OnlyIfOperator>,
+    op: Option<OnlyIfOperator>,
     #[knuffel(arguments, str)]
-    pub right: Vec<TemplateAwareString>,
+    right: Vec<TemplateAwareString>,
+    #[knuffel(children)]
+    conditions: Vec<Condition>,
+}
+
+impl<S> knuffel::Decode<S> for OnlyIf where S: ErrorSpan {
+    fn decode_node(node: &knuffel::ast::SpannedNode<S>, ctx: &mut Context<S>) -> Result<Self, DecodeError<S>> {
+        let raw = RawOnlyIf::decode_node(node, ctx)?;
+        if raw.left.is_none() && raw.conditions.is_empty() {
+            return Err(DecodeError::conversion(node, "an only-if rule needs a value to test or at least one condition"));
+        }
+
+        Ok(OnlyIf { left: raw.left, op: raw.op, right: raw.right, conditions: raw.conditions })
+    }
+}
+
+lazy_static! {
+    // Patterns for `matches` that are written out in the layout are the same
+    // on every card, so only compile each one once. Templated patterns can be
+    // different for every card, so they aren't kept.
+    static ref PATTERNS: Mutex<HashMap<String, Regex>> = Mutex::new(HashMap::new());
 }
 
 impl OnlyIf {
-    pub fn evaluate(&self, ctx: &Context) -> Result<bool, miette::Error> {
-        let left_val = self.left.render(ctx)?;
-        let right_vals: Vec<String> = self.right.iter().map(|tpl| tpl.render(ctx)).into_iter().collect::<Result<Vec<String>,TemplateError>>()?;
-
-        match self.op {
-            Some(OnlyIfOperator::Equal) => {
-                if let Some(right_val) = right_vals.first() {
-                    Ok(&left_val == right_val)
-                } else {
-                    Ok(false)
-                }
-            },
-            Some(OnlyIfOperator::NotEqual) => {
-                if let Some(right_val) = right_vals.first() {
-                    Ok(&left_val != right_val)
-                } else {
-                    Ok(false)
+    pub fn evaluate(&self, ctx: &TemplateContext) -> Result<bool, miette::Error> {
+        if let Some(ref left) = self.left {
+            if !compare(left, self.op.as_ref(), &self.right, ctx)? {
+                return Ok(false);
+            }
+        }
+
+        all(&self.conditions, ctx)
+    }
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub enum Condition {
+    Test(Test),
+    All(ConditionGroup),
+    Any(ConditionGroup),
+    // True unless all of its conditions hold
+    Not(ConditionGroup),
+}
+
+impl Condition {
+    pub fn evaluate(&self, ctx: &TemplateContext) -> Result<bool, miette::Error> {
+        match self {
+            Condition::Test(Test { left, op, right }) => compare(left, op.as_ref(), right, ctx),
+            Condition::All(ConditionGroup { conditions }) => all(conditions, ctx),
+            Condition::Any(ConditionGroup { conditions }) => {
+                for condition in conditions {
+                    if condition.evaluate(ctx)? {
+                        return Ok(true);
+                    }
                 }
+                Ok(false)
             },
-            Some(OnlyIfOperator::In) => Ok(right_vals.contains(&left_val)),
-            Some(OnlyIfOperator::NotIn) => Ok(!right_vals.contains(&left_val)),
-            None => Ok(!left_val.is_empty()),
+            Condition::Not(ConditionGroup { conditions }) => Ok(!all(conditions, ctx)?),
+        }
+    }
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Test {
+    #[knuffel(argument, str)]
+    pub left: TemplateAwareString,
+    #[knuffel(argument, str)]
+    pub op: Option<OnlyIfOperator>,
+    #[knuffel(arguments, str)]
+    pub right: Vec<TemplateAwareString>,
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct ConditionGroup {
+    #[knuffel(children)]
+    pub conditions: Vec<Condition>,
+}
+
+fn all(conditions: &[Condition], ctx: &TemplateContext) -> Result<bool, miette::Error> {
+    for condition in conditions {
+        if !condition.evaluate(ctx)? {
+            return Ok(false);
         }
     }
+    Ok(true)
+}
+
+pub fn compare(left: &TemplateAwareString, op: Option<&OnlyIfOperator>, right: &[TemplateAwareString], ctx: &TemplateContext) -> Result<bool, miette::Error> {
+    let left_val = left.render(ctx)?;
+    let right_vals: Vec<String> = right.iter().map(|tpl| tpl.render(ctx)).into_iter().collect::<Result<Vec<String>,TemplateError>>()?;
+
+    let op = match op {
+        Some(op) => op,
+        None => return Ok(!left_val.is_empty()),
+    };
+
+    if let OnlyIfOperator::In = op {
+        return Ok(right_vals.contains(&left_val));
+    }
+    if let OnlyIfOperator::NotIn = op {
+        return Ok(!right_vals.contains(&left_val));
+    }
+
+    let right_val = match right_vals.first() {
+        Some(right_val) => right_val,
+        None => return Ok(false),
+    };
+
+    match op {
+        OnlyIfOperator::Equal => Ok(&left_val == right_val),
+        OnlyIfOperator::NotEqual => Ok(&left_val != right_val),
+        OnlyIfOperator::LessThan => Ok(compare_numbers(&left_val, right_val, |l, r| l < r)),
+        OnlyIfOperator::LessThanOrEqual => Ok(compare_numbers(&left_val, right_val, |l, r| l <= r)),
+        OnlyIfOperator::GreaterThan => Ok(compare_numbers(&left_val, right_val, |l, r| l > r)),
+        OnlyIfOperator::GreaterThanOrEqual => Ok(compare_numbers(&left_val, right_val, |l, r| l >= r)),
+        OnlyIfOperator::Matches => Ok(pattern(&right[0], right_val)?.is_match(&left_val)),
+        OnlyIfOperator::Contains => Ok(left_val.contains(right_val.as_str())),
+        OnlyIfOperator::StartsWith => Ok(left_val.starts_with(right_val.as_str())),
+        OnlyIfOperator::EndsWith => Ok(left_val.ends_with(right_val.as_str())),
+        OnlyIfOperator::In | OnlyIfOperator::NotIn => unreachable!(),
+    }
+}
+
+// The pattern for a `matches` test, where `source` is `template` filled in
+fn pattern(template: &TemplateAwareString, source: &str) -> Result<Regex, miette::Error> {
+    if let TemplateAwareString::Template(_) = template {
+        return compile_pattern(source);
+    }
+
+    let mut patterns = PATTERNS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(pattern) = patterns.get(source) {
+        return Ok(pattern.clone());
+    }
+
+    let pattern = compile_pattern(source)?;
+    patterns.insert(source.to_string(), pattern.clone());
+    Ok(pattern)
+}
+
+fn compile_pattern(source: &str) -> Result<Regex, miette::Error> {
+    Regex::new(source).map_err(|err| miette!("Invalid pattern /{}/ in only-if rule: {}", source, err))
+}
+
+// Values that aren't numbers never satisfy a numeric comparison
+fn compare_numbers<F: Fn(f64, f64) -> bool>(left: &str, right: &str, cmp: F) -> bool {
+    match (left.trim().parse::<f64>(), right.trim().parse::<f64>()) {
+        (Ok(left), Ok(right)) => cmp(left, right),
+        _ => false,
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
@@ -48,6 +198,14 @@ pub enum OnlyIfOperator {
     NotEqual,
     In,
     NotIn,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    Matches,
+    Contains,
+    StartsWith,
+    EndsWith,
 }
 
 impl FromStr for OnlyIfOperator {
@@ -59,7 +217,91 @@ impl FromStr for OnlyIfOperator {
             "!=" => Ok(OnlyIfOperator::NotEqual),
             "in" => Ok(OnlyIfOperator::In),
             "not in" => Ok(OnlyIfOperator::NotIn),
-            _ => Err(miette!(r#"Invalid only-if operator. Expected one of `"="`, `"!="`, `"in"`, or `"not in"`."#))
+            "<" => Ok(OnlyIfOperator::LessThan),
+            "<=" => Ok(OnlyIfOperator::LessThanOrEqual),
+            ">" => Ok(OnlyIfOperator::GreaterThan),
+            ">=" => Ok(OnlyIfOperator::GreaterThanOrEqual),
+            "matches" => Ok(OnlyIfOperator::Matches),
+            "contains" => Ok(OnlyIfOperator::Contains),
+            "starts-with" => Ok(OnlyIfOperator::StartsWith),
+            "ends-with" => Ok(OnlyIfOperator::EndsWith),
+            _ => Err(miette!(r#"Invalid only-if operator. Expected one of `"="`, `"!="`, `"<"`, `"<="`, `">"`, `">="`, `"in"`, `"not in"`, `"matches"`, `"contains"`, `"starts-with"`, or `"ends-with"`."#))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use crate::layout::{model::testing::card_data, templates::new_registry};
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct TestCard {
+        name: &'static str,
+        power: i64,
+        keywords: &'static str,
+    }
+
+    #[derive(knuffel::Decode)]
+    struct Rules {
+        #[knuffel(children(name="only-if"))]
+        rules: Vec<OnlyIf>,
+    }
+
+    #[test]
+    fn it_evaluates_comparisons_and_combinators() -> miette::Result<()> {
+        let rules: Rules = knuffel::parse("test.layout", r#"
+            only-if "{{power}}" ">=" "10"
+            only-if "{{power}}" "<" "10"
+            only-if "{{name}}" "matches" "^Storm\\s"
+            only-if {
+                test "{{keywords}}" "contains" "Flying"
+                any {
+                    test "{{power}}" ">" "100"
+                    test "{{name}}" "starts-with" "Storm"
+                }
+            }
+            only-if "{{name}}" {
+                not {
+                    test "{{name}}" "ends-with" "Giant"
+                }
+            }
+            only-if "{{power}}" ">" "{{name}}"
+        "#)?;
+
+        let registry = new_registry();
+        let data = card_data(TestCard { name: "Storm Giant", power: 9, keywords: "Flying, Reach" })?;
+        let ctx = TemplateContext::new(&registry, &data);
+        let results = rules.rules.iter().map(|rule| rule.evaluate(&ctx)).collect::<Result<Vec<bool>, _>>()?;
+        assert_eq!(results, vec![false, true, true, true, false, false]);
+
+        assert!(knuffel::parse::<Rules>("test.layout", "only-if {}").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_only_caches_patterns_written_in_the_layout() -> miette::Result<()> {
+        let rules: Rules = knuffel::parse("test.layout", r#"
+            only-if "{{name}}" "matches" "^Cached Knight$"
+            only-if "{{name}}" "matches" "^{{name}}$"
+        "#)?;
+
+        let registry = new_registry();
+        for name in ["Cached Knight", "Uncached Squire"] {
+            let data = card_data(TestCard { name, power: 1, keywords: "" })?;
+            let ctx = TemplateContext::new(&registry, &data);
+            let results = rules.rules.iter().map(|rule| rule.evaluate(&ctx)).collect::<Result<Vec<bool>, _>>()?;
+            assert_eq!(results, vec![name == "Cached Knight", true]);
+        }
+
+        let patterns = PATTERNS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        assert!(patterns.contains_key("^Cached Knight$"));
+        assert!(!patterns.contains_key("^Uncached Squire$"));
+
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
 use std::{convert::Infallible, str::FromStr};
 
+use oxilangtag::LanguageTag;
+
 use crate::layout::model::styles::{only_if::OnlyIf, font::Font};
 
 use super::{color::ColorRef, font, TextStyle};
@@ -62,8 +64,58 @@ pub struct Align {
     pub alignment: Alignment,
 }
 
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct MaxLines {
+    #[knuffel(argument)]
+    pub lines: usize,
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Ellipsis {
+    #[knuffel(argument)]
+    pub ellipsis: String,
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Columns {
+    #[knuffel(argument)]
+    pub count: usize,
+    #[knuffel(property, default)]
+    pub gap: usize,
+}
+
+impl Columns {
+    pub fn column_width(&self, frame_width: f32) -> f32 {
+        let count = self.count.max(1);
+        let total_gap = (self.gap * (count - 1)) as f32;
+        (frame_width - total_gap).max(0.0) / (count as f32)
+    }
+
+    pub fn column_offset(&self, frame_width: f32, column: usize) -> f32 {
+        (column as f32) * (self.column_width(frame_width) + (self.gap as f32))
+    }
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct Hyphenate {
+    #[knuffel(argument, default=true)]
+    pub enabled: bool,
+    #[knuffel(property)]
+    pub lang: Option<String>,
+}
+
+#[derive(knuffel::Decode, PartialEq, Eq, Debug, Clone)]
+pub struct List {
+    #[knuffel(property)]
+    pub bullet: Option<String>,
+    #[knuffel(property)]
+    pub indent: Option<usize>,
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum Alignment {
+    Start,
+    End,
     Left,
     Center,
     Right,
@@ -75,6 +127,8 @@ impl FromStr for Alignment {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_ascii_lowercase().replace(" ", "").replace("-", "").as_str() {
+            "start" => Ok(Alignment::Start),
+            "end" => Ok(Alignment::End),
             "left" => Ok(Alignment::Left),
             "center" => Ok(Alignment::Center),
             "right" => Ok(Alignment::Right),
@@ -84,6 +138,36 @@ impl FromStr for Alignment {
     }
 }
 
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Direction {
+    LeftToRight,
+    RightToLeft,
+}
+
+// Scripts that are written right-to-left, as ISO 15924 codes
+const RIGHT_TO_LEFT_SCRIPTS: [&str; 6] = ["arab", "hebr", "nkoo", "syrc", "thaa", "adlm"];
+// Languages that are written right-to-left by default, as ISO 639 codes
+const RIGHT_TO_LEFT_LANGUAGES: [&str; 12] = ["ar", "ckb", "dv", "fa", "he", "iw", "ps", "sd", "syr", "ug", "ur", "yi"];
+
+impl Direction {
+    // Guesses the direction of text in a given language from its BCP 47 tag
+    // (e.g., "he", "ar-EG", "az-Arab"). An explicit script subtag wins over
+    // the language's usual script.
+    pub fn for_language(lang: &str) -> Direction {
+        let language_tag = match LanguageTag::parse(lang.replace("_", "-")) {
+            Ok(language_tag) => language_tag,
+            Err(_) => return Direction::LeftToRight,
+        };
+
+        let right_to_left = match language_tag.script() {
+            Some(script) => RIGHT_TO_LEFT_SCRIPTS.contains(&script.to_ascii_lowercase().as_str()),
+            None => RIGHT_TO_LEFT_LANGUAGES.contains(&language_tag.primary_language().to_ascii_lowercase().as_str()),
+        };
+
+        if right_to_left { Direction::RightToLeft } else { Direction::LeftToRight }
+    }
+}
+
 #[derive(Clone)]
 pub struct ComputedTextStyle<'a> {
     pub foreground: Option<&'a Foreground>,
@@ -94,6 +178,13 @@ pub struct ComputedTextStyle<'a> {
     pub font_weight: font::Weight,
     pub font_width: font::Width,
     pub font_style: Option<&'a str>,
+    pub max_lines: Option<usize>,
+    pub ellipsis: Option<&'a str>,
+    pub columns: Option<&'a Columns>,
+    pub list_bullet: Option<&'a str>,
+    pub list_indent: Option<usize>,
+    pub hyphenate: bool,
+    pub hyphenation_lang: Option<&'a str>,
     pub conditions: Vec<&'a OnlyIf>,
 }
 
@@ -123,6 +214,23 @@ impl<'a> ComputedTextStyle<'a> {
                 TextStyle::Background(bg) => {
                     self.background = Some(bg);
                 },
+                TextStyle::MaxLines(MaxLines { lines }) => {
+                    self.max_lines = Some(*lines);
+                },
+                TextStyle::Ellipsis(Ellipsis { ellipsis }) => {
+                    self.ellipsis = Some(ellipsis.as_str());
+                },
+                TextStyle::Columns(cols) => {
+                    self.columns = Some(cols);
+                },
+                TextStyle::List(List { bullet, indent }) => {
+                    self.list_bullet = bullet.as_ref().map(|s|s.as_str()).or(self.list_bullet);
+                    self.list_indent = indent.or(self.list_indent);
+                },
+                TextStyle::Hyphenate(Hyphenate { enabled, lang }) => {
+                    self.hyphenate = *enabled;
+                    self.hyphenation_lang = lang.as_ref().map(|s|s.as_str()).or(self.hyphenation_lang);
+                },
                 TextStyle::OnlyIf(cond) => {
                     self.conditions.push(cond);
                 }
@@ -137,12 +245,35 @@ impl<'a> Default for ComputedTextStyle<'a> {
             foreground: None,
             background: None,
             size: None,
-            align: Alignment::Left,
+            align: Alignment::Start,
             font_family: None,
             font_weight: font::Weight::Normal,
             font_width: font::Width::Normal,
             font_style: None,
+            max_lines: None,
+            ellipsis: None,
+            columns: None,
+            list_bullet: None,
+            list_indent: None,
+            hyphenate: false,
+            hyphenation_lang: None,
             conditions: vec![],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_guesses_text_direction_from_language_tags() -> () {
+        assert_eq!(Direction::for_language("en"), Direction::LeftToRight);
+        assert_eq!(Direction::for_language("en-US"), Direction::LeftToRight);
+        assert_eq!(Direction::for_language("he"), Direction::RightToLeft);
+        assert_eq!(Direction::for_language("AR_eg"), Direction::RightToLeft);
+        assert_eq!(Direction::for_language("az-Arab"), Direction::RightToLeft);
+        assert_eq!(Direction::for_language("pa-Arab-PK"), Direction::RightToLeft);
+        assert_eq!(Direction::for_language("uz-Latn"), Direction::LeftToRight);
+    }
+}
@@ -0,0 +1,28 @@
+// Scaffolding shared by the layout model's tests
+
+use handlebars::Context;
+use serde::Serialize;
+
+use super::{dimension::Extent, elements::Element, geometry::{Geometry, Insets}};
+
+#[derive(knuffel::Decode)]
+struct Elements {
+    #[knuffel(children)]
+    elements: Vec<Element>,
+}
+
+// Parse elements the same way as the top level of a layout
+pub fn parse_elements(source: &str) -> miette::Result<Vec<Element>> {
+    Ok(knuffel::parse::<Elements>("test.layout", source)?.elements)
+}
+
+// The whole of a 825x1125 card at 300 DPI, with a 37px cut and a 75px safe
+// zone
+pub fn card() -> Extent {
+    Geometry { width: 825, height: 1125, cut: Insets::uniform(37), safe: Insets::uniform(75), dpi: 300 }.extent()
+}
+
+// Card data for templates to be filled in from
+pub fn card_data(fields: impl Serialize) -> miette::Result<Context> {
+    Context::wraps(fields).map_err(|err| miette::miette!("{}", err))
+}
@@ -0,0 +1,395 @@
+use std::cmp::Ordering;
+
+use handlebars::{Context, Handlebars, Helper, HelperDef, JsonValue as Json, RenderContext, RenderError, ScopedJson};
+
+// Card data usually comes out of spreadsheets as strings, so every helper
+// here that works with numbers will also accept strings that look like them.
+//
+// The comparison helpers replace handlebars' built-in ones, which compare
+// strings lexically (so "10" < "9").
+pub fn register_all(hb: &mut Handlebars) -> () {
+    // Arithmetic
+    hb.register_helper("add", Box::new(ValueHelper(add)));
+    hb.register_helper("sub", Box::new(ValueHelper(sub)));
+    hb.register_helper("mul", Box::new(ValueHelper(mul)));
+    hb.register_helper("div", Box::new(ValueHelper(div)));
+    hb.register_helper("mod", Box::new(ValueHelper(modulo)));
+
+    // Comparisons
+    hb.register_helper("eq", Box::new(ValueHelper(eq)));
+    hb.register_helper("ne", Box::new(ValueHelper(ne)));
+    hb.register_helper("gt", Box::new(ValueHelper(gt)));
+    hb.register_helper("gte", Box::new(ValueHelper(gte)));
+    hb.register_helper("lt", Box::new(ValueHelper(lt)));
+    hb.register_helper("lte", Box::new(ValueHelper(lte)));
+
+    // Strings
+    hb.register_helper("upper", Box::new(ValueHelper(upper)));
+    hb.register_helper("lower", Box::new(ValueHelper(lower)));
+    hb.register_helper("title", Box::new(ValueHelper(title)));
+    hb.register_helper("replace", Box::new(ValueHelper(replace)));
+    hb.register_helper("trim", Box::new(ValueHelper(trim)));
+    hb.register_helper("pad", Box::new(ValueHelper(pad)));
+    hb.register_helper("repeat", Box::new(ValueHelper(repeat)));
+    hb.register_helper("pluralize", Box::new(ValueHelper(pluralize)));
+    hb.register_helper("number", Box::new(ValueHelper(number)));
+
+    // Lists and fallbacks
+    hb.register_helper("split", Box::new(ValueHelper(split)));
+    hb.register_helper("join", Box::new(ValueHelper(join)));
+    hb.register_helper("default", Box::new(ValueHelper(default)));
+}
+
+// Adapts a plain function into a helper that returns a value, so that it can
+// be used in subexpressions like `{{#if (gt (add power 1) 3)}}`.
+struct ValueHelper(fn(&Helper) -> Result<Json, RenderError>);
+
+impl HelperDef for ValueHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        (self.0)(h).map(ScopedJson::Derived)
+    }
+}
+
+fn param<'a>(h: &'a Helper, idx: usize) -> Result<&'a Json, RenderError> {
+    h.param(idx)
+        .map(|p| p.value())
+        .ok_or_else(|| RenderError::new(format!("`{}` helper: missing parameter {}", h.name(), idx + 1)))
+}
+
+fn number_param(h: &Helper, idx: usize) -> Result<f64, RenderError> {
+    let value = param(h, idx)?;
+    as_number(value)
+        .ok_or_else(|| RenderError::new(format!("`{}` helper: expected a number for parameter {}, but got {}", h.name(), idx + 1, value)))
+}
+
+// A count for a helper that builds up text, which can't be more than
+// MAX_COUNT so that a stray field can't fill memory
+fn count_param(h: &Helper, idx: usize) -> Result<usize, RenderError> {
+    let count = number_param(h, idx)?.max(0.0);
+    if count > MAX_COUNT as f64 {
+        return Err(RenderError::new(format!("`{}` helper: parameter {} can be at most {}, but got {}", h.name(), idx + 1, MAX_COUNT, count)));
+    }
+    Ok(count as usize)
+}
+
+const MAX_COUNT: usize = 10_000;
+
+fn string_param(h: &Helper, idx: usize) -> Result<String, RenderError> {
+    param(h, idx).map(as_string)
+}
+
+fn string_hash(h: &Helper, key: &str, default: &str) -> String {
+    h.hash_get(key).map(|v| as_string(v.value())).unwrap_or_else(|| default.to_string())
+}
+
+fn as_number(value: &Json) -> Option<f64> {
+    match value {
+        Json::Number(n) => n.as_f64(),
+        Json::String(s) => s.trim().parse::<f64>().ok().filter(|n| n.is_finite()),
+        _ => None,
+    }
+}
+
+fn as_string(value: &Json) -> String {
+    match value {
+        Json::Null => String::new(),
+        Json::String(s) => s.clone(),
+        Json::Array(items) => items.iter().map(as_string).collect::<Vec<String>>().join(", "),
+        other => other.to_string(),
+    }
+}
+
+// Whole numbers stay integers, so that `{{mul 2 3}}` renders as "6" rather
+// than "6.0".
+fn from_number(n: f64) -> Json {
+    if n.fract() == 0.0 && n.abs() < (i64::MAX as f64) {
+        Json::from(n as i64)
+    } else {
+        Json::from(n)
+    }
+}
+
+fn add(h: &Helper) -> Result<Json, RenderError> {
+    let mut sum = 0f64;
+    for idx in 0..h.params().len().max(1) {
+        sum += number_param(h, idx)?;
+    }
+    Ok(from_number(sum))
+}
+
+fn sub(h: &Helper) -> Result<Json, RenderError> {
+    Ok(from_number(number_param(h, 0)? - number_param(h, 1)?))
+}
+
+fn mul(h: &Helper) -> Result<Json, RenderError> {
+    let mut product = 1f64;
+    for idx in 0..h.params().len().max(1) {
+        product *= number_param(h, idx)?;
+    }
+    Ok(from_number(product))
+}
+
+fn div(h: &Helper) -> Result<Json, RenderError> {
+    let dividend = number_param(h, 0)?;
+    let divisor = number_param(h, 1)?;
+    if divisor == 0.0 {
+        return Err(RenderError::new(format!("`div` helper: cannot divide {} by zero", dividend)));
+    }
+    Ok(from_number(dividend / divisor))
+}
+
+fn modulo(h: &Helper) -> Result<Json, RenderError> {
+    let dividend = number_param(h, 0)?;
+    let divisor = number_param(h, 1)?;
+    if divisor == 0.0 {
+        return Err(RenderError::new(format!("`mod` helper: cannot divide {} by zero", dividend)));
+    }
+    Ok(from_number(dividend.rem_euclid(divisor)))
+}
+
+fn compare(h: &Helper) -> Result<Option<Ordering>, RenderError> {
+    let left = param(h, 0)?;
+    let right = param(h, 1)?;
+    Ok(match (as_number(left), as_number(right)) {
+        (Some(left), Some(right)) => left.partial_cmp(&right),
+        _ => Some(as_string(left).cmp(&as_string(right))),
+    })
+}
+
+fn eq(h: &Helper) -> Result<Json, RenderError> {
+    Ok(Json::from(compare(h)? == Some(Ordering::Equal)))
+}
+
+fn ne(h: &Helper) -> Result<Json, RenderError> {
+    Ok(Json::from(compare(h)? != Some(Ordering::Equal)))
+}
+
+fn gt(h: &Helper) -> Result<Json, RenderError> {
+    Ok(Json::from(compare(h)? == Some(Ordering::Greater)))
+}
+
+fn gte(h: &Helper) -> Result<Json, RenderError> {
+    Ok(Json::from(matches!(compare(h)?, Some(Ordering::Greater | Ordering::Equal))))
+}
+
+fn lt(h: &Helper) -> Result<Json, RenderError> {
+    Ok(Json::from(compare(h)? == Some(Ordering::Less)))
+}
+
+fn lte(h: &Helper) -> Result<Json, RenderError> {
+    Ok(Json::from(matches!(compare(h)?, Some(Ordering::Less | Ordering::Equal))))
+}
+
+fn upper(h: &Helper) -> Result<Json, RenderError> {
+    Ok(Json::from(string_param(h, 0)?.to_uppercase()))
+}
+
+fn lower(h: &Helper) -> Result<Json, RenderError> {
+    Ok(Json::from(string_param(h, 0)?.to_lowercase()))
+}
+
+fn title(h: &Helper) -> Result<Json, RenderError> {
+    let text = string_param(h, 0)?;
+    let mut titled = String::with_capacity(text.len());
+    let mut at_word_start = true;
+    for ch in text.chars() {
+        if at_word_start {
+            titled.extend(ch.to_uppercase());
+        } else {
+            titled.extend(ch.to_lowercase());
+        }
+        at_word_start = ch.is_whitespace();
+    }
+    Ok(Json::from(titled))
+}
+
+fn replace(h: &Helper) -> Result<Json, RenderError> {
+    let text = string_param(h, 0)?;
+    let from = string_param(h, 1)?;
+    let to = string_param(h, 2)?;
+    Ok(Json::from(text.replace(&from, &to)))
+}
+
+fn trim(h: &Helper) -> Result<Json, RenderError> {
+    Ok(Json::from(string_param(h, 0)?.trim()))
+}
+
+// {{pad value width fill="0" align="right"}}
+fn pad(h: &Helper) -> Result<Json, RenderError> {
+    let text = string_param(h, 0)?;
+    let width = count_param(h, 1)?;
+    let fill = string_hash(h, "fill", " ").chars().next().unwrap_or(' ');
+    let padding = width.saturating_sub(text.chars().count());
+    let (before, after) = match string_hash(h, "align", "right").as_str() {
+        "left" => (0, padding),
+        "center" => (padding / 2, padding - (padding / 2)),
+        _ => (padding, 0),
+    };
+
+    let mut padded = String::with_capacity(text.len() + padding);
+    padded.extend(std::iter::repeat(fill).take(before));
+    padded.push_str(&text);
+    padded.extend(std::iter::repeat(fill).take(after));
+    Ok(Json::from(padded))
+}
+
+fn repeat(h: &Helper) -> Result<Json, RenderError> {
+    let text = string_param(h, 0)?;
+    let count = count_param(h, 1)?;
+    Ok(Json::from(text.repeat(count)))
+}
+
+// {{pluralize count "card"}}, or {{pluralize count "mouse" "mice"}} for
+// irregular plurals
+fn pluralize(h: &Helper) -> Result<Json, RenderError> {
+    let count = number_param(h, 0)?;
+    let singular = string_param(h, 1)?;
+    if count == 1.0 {
+        return Ok(Json::from(singular));
+    }
+
+    let plural = match h.param(2) {
+        Some(plural) => as_string(plural.value()),
+        None => {
+            let lower = singular.to_lowercase();
+            let ends_with_consonant_y = lower.ends_with('y') && !lower.ends_with("ay") && !lower.ends_with("ey") && !lower.ends_with("oy") && !lower.ends_with("uy");
+            if ends_with_consonant_y {
+                format!("{}ies", &singular[..singular.len() - 1])
+            } else if ["s", "x", "z", "ch", "sh"].iter().any(|suffix| lower.ends_with(suffix)) {
+                format!("{}es", singular)
+            } else {
+                format!("{}s", singular)
+            }
+        },
+    };
+    Ok(Json::from(plural))
+}
+
+// {{number value decimals=2 separator=","}}
+fn number(h: &Helper) -> Result<Json, RenderError> {
+    let value = number_param(h, 0)?;
+    let decimals = match h.hash_get("decimals") {
+        Some(decimals) => as_number(decimals.value())
+            .ok_or_else(|| RenderError::new("`number` helper: decimals must be a number"))?
+            .max(0.0) as usize,
+        None => 0,
+    };
+    let separator = string_hash(h, "separator", "");
+
+    let formatted = format!("{:.*}", decimals, value.abs());
+    let (whole, fraction) = formatted.split_at(formatted.find('.').unwrap_or(formatted.len()));
+    let mut grouped = String::with_capacity(formatted.len() * 2);
+    if value < 0.0 && formatted.chars().any(|ch| ch != '0' && ch != '.') {
+        grouped.push('-');
+    }
+    for (idx, digit) in whole.chars().enumerate() {
+        if idx > 0 && (whole.len() - idx) % 3 == 0 {
+            grouped.push_str(&separator);
+        }
+        grouped.push(digit);
+    }
+    grouped.push_str(fraction);
+    Ok(Json::from(grouped))
+}
+
+// {{#each (split keywords ",")}}
+fn split(h: &Helper) -> Result<Json, RenderError> {
+    let value = param(h, 0)?;
+    if value.is_array() {
+        return Ok(value.clone());
+    }
+
+    let separator = match h.param(1) {
+        Some(separator) => as_string(separator.value()),
+        None => ",".to_string(),
+    };
+    let text = as_string(value);
+    Ok(Json::from(
+        text.split(separator.as_str())
+            .map(|item| item.trim())
+            .filter(|item| !item.is_empty())
+            .map(|item| Json::from(item))
+            .collect::<Vec<Json>>()
+    ))
+}
+
+fn join(h: &Helper) -> Result<Json, RenderError> {
+    let separator = match h.param(1) {
+        Some(separator) => as_string(separator.value()),
+        None => ", ".to_string(),
+    };
+    Ok(Json::from(match param(h, 0)? {
+        Json::Array(items) => items.iter().map(as_string).collect::<Vec<String>>().join(&separator),
+        other => as_string(other),
+    }))
+}
+
+fn default(h: &Helper) -> Result<Json, RenderError> {
+    let value = h.param(0).map(|p| p.value()).unwrap_or(&Json::Null);
+    let is_blank = match value {
+        Json::Null => true,
+        Json::String(s) => s.trim().is_empty(),
+        _ => false,
+    };
+    if is_blank {
+        param(h, 1).map(|fallback| fallback.clone())
+    } else {
+        Ok(value.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use crate::layout::{model::testing::card_data, templates::{TemplateAwareString, TemplateContext, new_registry}};
+
+    #[derive(Serialize)]
+    struct TestCard {
+        name: &'static str,
+        power: &'static str,
+        cost: &'static str,
+        keywords: &'static str,
+        blank: &'static str,
+    }
+
+    fn render(template: &str) -> miette::Result<String> {
+        let card = TestCard { name: "storm giant", power: "9", cost: "1234.5", keywords: "Flying, Reach", blank: "" };
+        let ctx = card_data(card)?;
+        let registry = new_registry();
+        Ok(TemplateAwareString::new(template.to_string()).render(&TemplateContext::new(&registry, &ctx))?)
+    }
+
+    #[test]
+    fn it_does_arithmetic_and_comparisons() -> miette::Result<()> {
+        assert_eq!(render("Deals {{mul power 2}} damage")?, "Deals 18 damage");
+        assert_eq!(render("{{add power 1 2}} {{sub power 10}} {{div power 2}} {{mod power 4}}")?, "12 -1 4.5 1");
+        assert_eq!(render("{{#if (gt power 10)}}big{{else}}small{{/if}}")?, "small");
+        assert_eq!(render("{{#if (lte power \"9\")}}yes{{/if}}")?, "yes");
+        assert!(render("{{div power 0}}").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_formats_strings_and_lists() -> miette::Result<()> {
+        assert_eq!(render("{{title name}} / {{upper name}} / {{replace name \" \" \"_\"}}")?, "Storm Giant / STORM GIANT / storm_giant");
+        assert_eq!(render("[{{pad power 3 fill=\"0\"}}] [{{pad power 3 align=\"left\"}}]")?, "[009] [9  ]");
+        assert_eq!(render("{{number cost decimals=2 separator=\",\"}}")?, "1,234.50");
+        assert_eq!(render("{{power}} {{pluralize power \"ally\"}}, 1 {{pluralize 1 \"box\"}}")?, "9 allies, 1 box");
+        assert_eq!(render("{{repeat \"*\" power}}")?, "*********");
+        assert!(render("{{repeat \"*\" 1000000000}}").is_err());
+        assert!(render("{{pad power 100000}}").is_err());
+        assert_eq!(render("{{#each (split keywords)}}<{{this}}>{{/each}}")?, "<Flying><Reach>");
+        assert_eq!(render("{{join (split keywords \",\") \" & \"}}")?, "Flying & Reach");
+        assert_eq!(render("{{default blank \"none\"}} {{default name \"none\"}}")?, "none storm giant");
+
+        Ok(())
+    }
+}
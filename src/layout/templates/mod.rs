@@ -0,0 +1,234 @@
+use std::{str::FromStr, error::Error};
+
+use handlebars::{BlockContext, Context, Handlebars, JsonValue as Json, RenderContext, Renderable, StringOutput, Template};
+use miette::{SourceOffset, Diagnostic};
+use thiserror::Error;
+
+mod helpers;
+pub mod scripts;
+
+// Everything needed to fill in a template: the project's handlebars registry,
+// which carries its helpers and partials, the data being rendered, and the
+// scopes (if any) that the template is nested inside of.
+pub struct TemplateContext<'a> {
+    pub registry: &'a Handlebars<'static>,
+    pub data: &'a Context,
+    pub scopes: &'a [Scope],
+}
+
+impl<'a> TemplateContext<'a> {
+    pub fn new(registry: &'a Handlebars<'static>, data: &'a Context) -> TemplateContext<'a> {
+        TemplateContext { registry, data, scopes: &[] }
+    }
+
+    pub fn with_scopes(self, scopes: &'a [Scope]) -> TemplateContext<'a> {
+        TemplateContext { scopes, ..self }
+    }
+
+    // Look a value up by a path, the same way a template would see it: plain
+    // names (like `name` or `stats.power`) and `this` refer to the innermost
+    // scope, each `../` steps out a scope, and `@root` is the card's data.
+    pub fn lookup(&self, path: &str) -> Option<&'a Json> {
+        let mut depth = self.scopes.len();
+        let mut path = path.trim();
+        if let Some(rest) = path.strip_prefix("@root") {
+            depth = 0;
+            path = rest.trim_start_matches('.');
+        }
+        while let Some(rest) = path.strip_prefix("../") {
+            depth = depth.saturating_sub(1);
+            path = rest;
+        }
+        let mut value = match depth {
+            0 => self.data.data(),
+            depth => &self.scopes[depth - 1].this,
+        };
+        for segment in path.split('.').filter(|segment| !segment.is_empty()) {
+            value = match (segment, value) {
+                ("this", value) => value,
+                (index, Json::Array(items)) => items.get(index.parse::<usize>().ok()?)?,
+                (name, value) => value.get(name)?,
+            };
+        }
+        Some(value)
+    }
+}
+
+// A level of nesting that changes what templates see, the same way that a
+// handlebars block does: `{{this}}` and plain names refer to the scope's
+// value, `@`-variables to its locals, and the enclosing data is available as
+// `{{../name}}` or `{{@root.name}}`.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    pub this: Json,
+    pub locals: Vec<(&'static str, Json)>,
+}
+
+impl Scope {
+    pub fn new(this: Json) -> Scope {
+        Scope { this, locals: vec![] }
+    }
+
+    // One pass through a loop, like a single copy of a repeated element, with
+    // the same variables as inside an `{{#each}}` block
+    pub fn loop_item(this: Json, index: usize, count: usize) -> Scope {
+        Scope {
+            this,
+            locals: vec![
+                ("index", Json::from(index)),
+                ("first", Json::from(index == 0)),
+                ("last", Json::from(index + 1 == count)),
+            ],
+        }
+    }
+
+    fn to_block<'reg>(&self) -> BlockContext<'reg> {
+        let mut block = BlockContext::new();
+        block.set_base_value(self.this.clone());
+        for (name, value) in &self.locals {
+            block.set_local_var(name, value.clone());
+        }
+        block
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum TemplateAwareString {
+    RawString(String),
+    Template(String),
+}
+
+impl TemplateAwareString {
+    pub fn new(contents: String) -> TemplateAwareString {
+        if contents.contains("{{") {
+            TemplateAwareString::Template(contents)
+        } else {
+            TemplateAwareString::RawString(contents)
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        match self {
+            Self::RawString(s) => s.as_str(),
+            Self::Template(tpl) => tpl.as_str(),
+        }
+    }
+
+    pub fn render(&self, ctx: &TemplateContext) -> Result<String, TemplateError> {
+        match self {
+            Self::RawString(s) => Ok(s.clone()),
+            Self::Template(tpl) => {
+                let err_helper = ErrorHelper::of(tpl.as_str());
+                if ctx.scopes.is_empty() {
+                    ctx.registry
+                        .render_template_with_context(&tpl, ctx.data)
+                        .map_err(|err| err_helper.from_hb(err))
+                } else {
+                    render_in_scopes(tpl, ctx).map_err(|err| err_helper.from_hb(err))
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for TemplateAwareString {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(TemplateAwareString::new(s.to_string()))
+    }
+}
+
+// Handlebars only exposes block variables to helpers, so rendering inside a
+// loop means driving the template by hand with the loop's blocks pushed.
+fn render_in_scopes(tpl: &str, ctx: &TemplateContext) -> Result<String, handlebars::RenderError> {
+    let template = Template::compile(tpl)?;
+    let mut output = StringOutput::new();
+    let mut render_context = RenderContext::new(None);
+    for scope in ctx.scopes {
+        render_context.push_block(scope.to_block());
+    }
+    template.render(ctx.registry, ctx.data, &mut render_context, &mut output)?;
+    output.into_string().map_err(handlebars::RenderError::from)
+}
+
+// Lets properties take either a template or a plain number or boolean, which
+// is read as the equivalent string
+impl<S> knuffel::DecodeScalar<S> for TemplateAwareString where S: knuffel::traits::ErrorSpan {
+    fn type_check(type_name: &Option<knuffel::span::Spanned<knuffel::ast::TypeName, S>>, ctx: &mut knuffel::decode::Context<S>) {
+        if let Some(type_name) = type_name {
+            ctx.emit_error(knuffel::errors::DecodeError::unsupported(type_name, "type annotations are not supported here"));
+        }
+    }
+
+    fn raw_decode(literal: &knuffel::span::Spanned<knuffel::ast::Literal, S>, _ctx: &mut knuffel::decode::Context<S>)
+        -> Result<Self, knuffel::errors::DecodeError<S>> {
+        use knuffel::ast::Literal;
+        match **literal {
+            Literal::String(ref contents) => Ok(TemplateAwareString::new(contents.to_string())),
+            Literal::Int(ref integer) => {
+                let integer: i64 = integer.try_into().map_err(|err| knuffel::errors::DecodeError::conversion(literal, err))?;
+                Ok(TemplateAwareString::RawString(integer.to_string()))
+            },
+            Literal::Decimal(ref decimal) => {
+                let decimal: f64 = decimal.try_into().map_err(|err| knuffel::errors::DecodeError::conversion(literal, err))?;
+                Ok(TemplateAwareString::RawString(decimal.to_string()))
+            },
+            Literal::Bool(boolean) => Ok(TemplateAwareString::RawString(boolean.to_string())),
+            Literal::Null => Ok(TemplateAwareString::RawString(String::new())),
+        }
+    }
+}
+
+pub fn new_registry() -> Handlebars<'static> {
+    let mut hb = Handlebars::new();
+    hb.register_escape_fn(handlebars::no_escape);
+    helpers::register_all(&mut hb);
+    hb
+}
+
+#[derive(Error, Diagnostic, Debug)]
+pub enum TemplateError {
+    #[error(transparent)]
+    UnspecifiedError(#[from] handlebars::RenderError),
+    #[error("error while filling in template: {description}")]
+    TemplateErrorAtLocation {
+        description: String,
+        #[source_code]
+        template_source: String,
+        #[label("error occurred here")]
+        offset: SourceOffset,
+    },
+}
+
+struct ErrorHelper<'a> {
+    template_source: &'a str
+}
+
+impl ErrorHelper<'_> {
+    fn of(template_source: &str) -> ErrorHelper {
+        ErrorHelper { template_source }
+    }
+
+    fn from_hb(&self, render_err: handlebars::RenderError) -> TemplateError {
+        match render_err.source() {
+            Some(inner) => match inner.downcast_ref::<handlebars::TemplateError>() {
+                Some(template_err) => match (template_err.line_no, template_err.column_no) {
+                    (Some(line), Some(column)) =>
+                        TemplateError::TemplateErrorAtLocation {
+                            description: format!("{}", template_err.reason()),
+                            template_source: self.template_source.to_string(),
+                            offset: SourceOffset::from_location(
+                                self.template_source,
+                                line,
+                                column
+                            ),
+                        },
+                    _ => TemplateError::UnspecifiedError(render_err),
+                },
+                None => TemplateError::UnspecifiedError(render_err),
+            },
+            None => TemplateError::UnspecifiedError(render_err),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use handlebars::{Context, Handlebars, Helper, HelperDef, JsonValue as Json, RenderContext, RenderError, ScopedJson};
+use miette::{Diagnostic, SourceOffset};
+use rhai::{Dynamic, Engine, FnAccess, Scope, AST};
+use thiserror::Error;
+
+// Every public function in a script becomes a helper with the same name,
+// called with the helper's positional parameters. For example, a project
+// script containing
+//
+//     fn cost(generic, colors) { ... }
+//
+// can be used from any template as `{{cost generic_cost color_cost}}`.
+pub fn register_script(hb: &mut Handlebars, file_name: &str, source: &str) -> Result<usize, ScriptError> {
+    let engine = Arc::new(limited_engine());
+    let ast = engine.compile(source).map_err(|err| {
+        let position = err.position();
+        ScriptError::CompileError {
+            file_name: file_name.to_string(),
+            description: err.err_type().to_string(),
+            script_source: source.to_string(),
+            offset: SourceOffset::from_location(
+                source,
+                position.line().unwrap_or(1),
+                position.position().unwrap_or(1),
+            ),
+        }
+    })?;
+    let ast = Arc::new(ast);
+
+    let mut helper_count = 0usize;
+    for function in ast.iter_functions().filter(|function| function.access != FnAccess::Private) {
+        log::debug!("Registering helper \"{}\" from script {}", function.name, file_name);
+        hb.register_helper(function.name, Box::new(ScriptFunctionHelper {
+            engine: engine.clone(),
+            ast: ast.clone(),
+            function_name: function.name.to_string(),
+        }));
+        helper_count += 1;
+    }
+
+    Ok(helper_count)
+}
+
+// Scripts run once per helper call, for every card, so one that never
+// finishes (or recurses without end, or builds an enormous string) would
+// stall the whole render. These limits turn that into an error instead.
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_CALL_LEVELS: usize = 64;
+const MAX_EXPRESSION_DEPTH: usize = 64;
+const MAX_FUNCTION_EXPRESSION_DEPTH: usize = 32;
+const MAX_STRING_SIZE: usize = 1_000_000;
+const MAX_ARRAY_SIZE: usize = 10_000;
+const MAX_MAP_SIZE: usize = 10_000;
+
+fn limited_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_expr_depths(MAX_EXPRESSION_DEPTH, MAX_FUNCTION_EXPRESSION_DEPTH);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_ARRAY_SIZE);
+    engine.set_max_map_size(MAX_MAP_SIZE);
+    engine
+}
+
+struct ScriptFunctionHelper {
+    engine: Arc<Engine>,
+    ast: Arc<AST>,
+    function_name: String,
+}
+
+impl HelperDef for ScriptFunctionHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        let args = h.params().iter()
+            .map(|param| rhai::serde::to_dynamic(param.value()))
+            .collect::<Result<Vec<Dynamic>, _>>()
+            .map_err(|err| RenderError::new(format!("`{}` helper: couldn't pass parameters to script: {}", self.function_name, err)))?;
+
+        let result = self.engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, &self.function_name, args)
+            .map_err(|err| RenderError::new(format!("`{}` helper: {}", self.function_name, err)))?;
+
+        rhai::serde::from_dynamic::<Json>(&result)
+            .map(ScopedJson::Derived)
+            .map_err(|err| RenderError::new(format!("`{}` helper: couldn't read the script's result: {}", self.function_name, err)))
+    }
+}
+
+#[derive(Error, Diagnostic, Debug)]
+pub enum ScriptError {
+    #[error("couldn't compile helper script {file_name}: {description}")]
+    CompileError {
+        file_name: String,
+        description: String,
+        #[source_code]
+        script_source: String,
+        #[label("error occurred here")]
+        offset: SourceOffset,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::layout::{model::testing::card_data, templates::{TemplateAwareString, TemplateContext, new_registry}};
+
+    use super::*;
+
+    #[test]
+    fn it_registers_script_functions_as_helpers() -> miette::Result<()> {
+        let mut registry = new_registry();
+        let helper_count = register_script(&mut registry, "helpers.rhai", r#"
+            fn cost(generic, colors) {
+                let pips = "";
+                for color in colors.split(",") {
+                    color.trim();
+                    pips += "{" + color + "}";
+                }
+                if generic > 0 { "{" + generic + "}" + pips } else { pips }
+            }
+
+            private fn unused() { 0 }
+        "#)?;
+        assert_eq!(helper_count, 1);
+
+        let fields = HashMap::from([("generic", Json::from(2)), ("colors", Json::from("R, G"))]);
+        let data = card_data(fields)?;
+        let ctx = TemplateContext::new(&registry, &data);
+        assert_eq!(TemplateAwareString::new("Cost: {{cost generic colors}}".to_string()).render(&ctx)?, "Cost: {2}{R}{G}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_stops_scripts_that_never_finish() -> miette::Result<()> {
+        let mut registry = new_registry();
+        register_script(&mut registry, "runaway.rhai", r#"
+            fn spin() { loop {} }
+            fn dive(n) { dive(n + 1) }
+        "#)?;
+
+        let data = card_data(HashMap::<String, Json>::new())?;
+        let ctx = TemplateContext::new(&registry, &data);
+        assert!(TemplateAwareString::new("{{spin}}".to_string()).render(&ctx).is_err());
+        assert!(TemplateAwareString::new("{{dive 0}}".to_string()).render(&ctx).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_reports_script_syntax_errors() -> () {
+        let mut registry = new_registry();
+        assert!(matches!(
+            register_script(&mut registry, "broken.rhai", "fn broken( { }"),
+            Err(ScriptError::CompileError { .. }),
+        ));
+    }
+}
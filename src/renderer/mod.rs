@@ -22,3 +22,28 @@ pub trait Renderer {
         I: Iterator<Item = &'a Self::SingleCard<'a>>,
         P: AsRef<Path> + Debug;
 }
+
+// Which layers of a layout to draw. Elements take the layer of the nearest
+// element that names one, starting with themselves. When `only` isn't empty,
+// just those layers are drawn (so elements without a layer aren't), and
+// layers in `skip` are never drawn. Hidden elements still take up room, so
+// the rest of the card is laid out the same way.
+#[derive(Debug, Clone)]
+pub struct LayerFilter {
+    pub only: Vec<String>,
+    pub skip: Vec<String>,
+}
+
+impl LayerFilter {
+    pub fn allows(&self, layer: Option<&str>) -> bool {
+        let included = self.only.is_empty() || layer.is_some_and(|layer| self.only.iter().any(|name| name == layer));
+        let skipped = layer.is_some_and(|layer| self.skip.iter().any(|name| name == layer));
+        included && !skipped
+    }
+}
+
+impl Default for LayerFilter {
+    fn default() -> Self {
+        LayerFilter { only: vec![], skip: vec![] }
+    }
+}
@@ -1,57 +1,254 @@
-use skia_safe::{Canvas, Paint, Color4f, IRect, PaintStyle, textlayout::{TextStyle as SkTextStyle, FontCollection, ParagraphBuilder, ParagraphStyle}, FontMgr, Rect, ClipOp, Color as SkiaColor, PathEffect, FontStyle, font_style::Slant};
+use miette::{miette, WrapErr};
+use skia_safe::{Canvas, Paint, Color4f, PaintStyle, textlayout::{TextStyle as SkTextStyle, FontCollection, Paragraph, ParagraphBuilder, ParagraphStyle, TextDirection}, FontMgr, Rect, ClipOp, Color as SkiaColor, PathEffect, FontStyle, font_style::Slant, Path};
 
-use crate::{layout::model::{elements::{Element, shapes::Rectangle, text::Text, containers::Box, image::{Image, Scale}, Frame}, styles::{color::{ColorRef, Color as CardboardColor}, stroke::DashPattern, text::{Foreground, Background as TextBackground, Alignment, ComputedTextStyle, Size, Units}, font::{Weight, Width}, PathStyle, stroke::Stroke, solid::Solid, ImageStyle}}, data::{card::Card, project::{Project}}, format::{self, FormattedTextInstruction}};
+use crate::{layout::model::{elements::{in_draw_order, Element, text::Text, containers::{Axis, Box, Grid, Repeat, Stack, StackAlignment}, component::Use, image::{Image, Scale}, codes::{Barcode, QrCode, Symbol}, table::{Table, TableLayout}, Bounds}, dimension::Extent, geometry::Geometry, styles::{color::{ColorRef, Color as CardboardColor}, stroke::DashPattern, text::{Foreground, Background as TextBackground, Alignment, Columns, ComputedTextStyle, Direction, Size, Units}, font::{Weight, Width}, PathStyle, stroke::Stroke, solid::Solid, ImageStyle, TextStyle, inline, only_if::OnlyIf}}, data::{card::Card, project::{Project}}, layout::templates::{Scope, TemplateAwareString, TemplateContext}, format::{self, FormattedTextInstruction, ListMarker, StyleTag}};
 
 use super::{SkiaRendererError, SkiaRenderer};
 
+// Skia's default font size, used when no size style applies
+const DEFAULT_FONT_SIZE: f32 = 14.0f32;
+const DEFAULT_LIST_BULLET: &str = "\u{2022}";
+
+const DEFAULT_HYPHENATION_LANGUAGE: &str = "en-US";
+// How many times to lay a paragraph out again while the hyphens drawn at
+// line ends move its line breaks around
+const MAX_HYPHENATION_PASSES: usize = 3;
+// How much of a text element's contents to quote when warning about it
+const TEXT_EXCERPT_LENGTH: usize = 40;
+
+fn conditions_hold<'c>(conditions: impl Iterator<Item = &'c OnlyIf>, ctx: &TemplateContext) -> Result<bool, miette::Error> {
+    for condition in conditions {
+        if !condition.evaluate(ctx)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn to_rect(bounds: &Bounds) -> Rect {
+    Rect::from_xywh(bounds.x, bounds.y, bounds.w, bounds.h)
+}
+
+// Point out a text element in a warning by its id (if it has one), the
+// template it's filled in from, and the start of what it says
+fn describe_text(text: &Text, rendered: &str) -> String {
+    let mut description = match text.id {
+        Some(ref id) => format!("text \"{}\"", id),
+        None => "text".to_string(),
+    };
+    if let TemplateAwareString::Template(ref source) = text.contents {
+        description.push_str(&format!(" from \"{}\"", source));
+    }
+    let rendered = rendered.split_whitespace().collect::<Vec<_>>().join(" ");
+    let excerpt: String = rendered.chars().take(TEXT_EXCERPT_LENGTH).collect();
+    let ellipsis = if excerpt.len() < rendered.len() { "..." } else { "" };
+    format!("{} (\"{}{}\")", description, excerpt, ellipsis)
+}
+
+// A piece of a paragraph, recorded so that the paragraph can be rebuilt
+enum TextRun {
+    Text(String),
+    PushStyle(SkTextStyle),
+    Pop,
+}
+
+struct TextBlock {
+    paragraph: Paragraph,
+    marker: Option<Paragraph>,
+    indent: f32,
+    direction: Direction,
+}
+
+struct LaidOutText {
+    blocks: Vec<TextBlock>,
+    columns: Option<Columns>,
+    // Whether some of the text was cut off by a line limit
+    truncated: bool,
+}
+
+impl LaidOutText {
+    fn height(&self) -> f32 {
+        self.blocks.iter().map(|block| block.paragraph.height()).sum()
+    }
+}
+
 pub struct CardRenderContext<'a> {
     card: &'a Card,
     project: &'a Project,
     dpi: usize,
+    // Where the box currently being drawn into is on the card. The canvas is
+    // translated to match, but its transform can't be read back once anything
+    // scales or rotates it.
+    extent: Extent,
     renderer: &'a mut SkiaRenderer,
     base_text_styles: ComputedTextStyle<'a>,
+    lang: Option<&'a str>,
+    direction: Direction,
+    // The repeats and components currently being drawn, outermost first
+    scopes: Vec<Scope>,
+    components_in_use: Vec<&'a str>,
+    // The layer of the element currently being drawn
+    layer: Option<String>,
 }
 
 impl<'a> CardRenderContext<'a> {
-    pub fn new(card: &'a Card, project: &'a Project, dpi: usize, renderer: &'a mut SkiaRenderer, base_text_styles: ComputedTextStyle<'a>) -> CardRenderContext<'a> {
-        CardRenderContext { card, project, dpi, renderer, base_text_styles }
-    }
-
-    pub fn draw_elements(&mut self, canvas: &mut Canvas, elements: &Vec<Element>, frame_width: usize, frame_height: usize) -> Result<(), miette::Error> {
-        for element in elements {
-            match element {
-                Element::Background(bg) => self.draw_rect(canvas, &bg.to_rect(frame_width, frame_height))?,
-                Element::Rectangle(rect) => self.draw_rect(canvas, rect)?,
-                Element::Image(image_frame) => self.draw_image(canvas, image_frame)?,
-                Element::Text(text) => self.draw_text(canvas, text)?,
-                Element::Box(bx) => self.draw_box(canvas, bx)?,
-            }
+    pub fn new(card: &'a Card, project: &'a Project, geometry: &Geometry, renderer: &'a mut SkiaRenderer, base_text_styles: ComputedTextStyle<'a>) -> CardRenderContext<'a> {
+        // A card's own language takes precedence over the project's
+        let lang = card.lang().or(project.lang.as_deref());
+        let direction = lang.map_or(Direction::LeftToRight, Direction::for_language);
+        CardRenderContext {
+            card,
+            project,
+            dpi: geometry.dpi,
+            extent: geometry.extent(),
+            renderer,
+            base_text_styles,
+            lang,
+            direction,
+            scopes: vec![],
+            components_in_use: vec![],
+            layer: None,
+        }
+    }
+
+    pub fn draw_elements(&mut self, canvas: &mut Canvas, elements: &Vec<Element>, frame_width: f32, frame_height: f32) -> Result<(), miette::Error> {
+        for element in in_draw_order(elements) {
+            self.draw_element(canvas, element, frame_width, frame_height)?;
+        }
+        Ok(())
+    }
+
+    fn draw_element(&mut self, canvas: &mut Canvas, element: &Element, frame_width: f32, frame_height: f32) -> Result<(), miette::Error> {
+        let outer_layer = self.enter_layer(element);
+        let result = self.draw_element_in_layer(canvas, element, frame_width, frame_height);
+        self.layer = outer_layer;
+        result
+    }
+
+    fn draw_element_in_layer(&mut self, canvas: &mut Canvas, element: &Element, frame_width: f32, frame_height: f32) -> Result<(), miette::Error> {
+        match element {
+            Element::Background(bg) => {
+                if self.layer_is_drawn() {
+                    self.draw_rect(canvas, &bg.style, Bounds { x: 0.0, y: 0.0, w: frame_width, h: frame_height })?;
+                }
+            },
+            Element::When(when) => {
+                if let Some(contents) = when.select(&self.template_context()?)? {
+                    self.draw_elements(canvas, contents, frame_width, frame_height)?;
+                }
+            },
+            Element::Switch(switch) => {
+                if let Some(contents) = switch.select(&self.template_context()?)? {
+                    self.draw_elements(canvas, contents, frame_width, frame_height)?;
+                }
+            },
+            Element::Repeat(repeat) => self.draw_repeat(canvas, repeat, frame_width, frame_height)?,
+            _ => {
+                let extent = self.extent_at(Bounds { x: 0.0, y: 0.0, w: frame_width, h: frame_height });
+                if let Some(bounds) = self.frame_of(element, extent)? {
+                    self.draw_in_bounds(canvas, element, bounds)?;
+                }
+            },
         }
         Ok(())
     }
+
+    // Move into an element's layer, if it names one, and return the layer to
+    // go back to once it's been drawn
+    fn enter_layer(&mut self, element: &Element) -> Option<String> {
+        match element.layer() {
+            Some(layer) => self.layer.replace(layer.to_string()),
+            None => self.layer.clone(),
+        }
+    }
+
+    fn layer_is_drawn(&self) -> bool {
+        self.renderer.layers.allows(self.layer.as_deref())
+    }
+
+    // The extent of a box at `bounds` in the canvas's current coordinates
+    fn extent_at(&self, bounds: Bounds) -> Extent {
+        self.extent.within(&bounds)
+    }
+
+    // The resolved frame of an element that's positioned by one, inside of a
+    // box with the given extent
+    fn frame_of(&self, element: &Element, extent: Extent) -> Result<Option<Bounds>, miette::Error> {
+        let ctx = self.template_context()?;
+        Ok(match element {
+            Element::Rectangle(rect) => Some(rect.bounds(&ctx, extent)?),
+            Element::Image(image_frame) => Some(image_frame.frame.bounds(&ctx, extent)?),
+            Element::Text(text) => Some(text.frame.bounds(&ctx, extent)?),
+            Element::Qrcode(qr) => Some(qr.frame.bounds(&ctx, extent)?),
+            Element::Barcode(barcode) => Some(barcode.frame.bounds(&ctx, extent)?),
+            Element::Table(table) => Some(table.frame.bounds(&ctx, extent)?),
+            Element::Box(bx) => Some(bx.bounds(&ctx, extent)?),
+            Element::Row(stack) | Element::Column(stack) => Some(stack.bounds(&ctx, extent)?),
+            Element::Grid(grid) => Some(grid.bounds(&ctx, extent)?),
+            Element::Use(usage) => match self.project.component_named(&usage.component) {
+                Some(component) => Some(usage.bounds(component, &ctx, extent)?),
+                None => {
+                    log::warn!("While rendering card {}: no component named \"{}\" was found. Skipping it.", self.card.id, usage.component);
+                    None
+                },
+            },
+            Element::Background(_) | Element::When(_) | Element::Switch(_) | Element::Repeat(_) => None,
+        })
+    }
+
+    // Draw an element that's positioned by a frame at the given bounds, which
+    // may differ from its frame when it's laid out by a stack
+    fn draw_in_bounds(&mut self, canvas: &mut Canvas, element: &Element, bounds: Bounds) -> Result<(), miette::Error> {
+        let outer_layer = self.enter_layer(element);
+        let result = self.draw_in_bounds_in_layer(canvas, element, bounds);
+        self.layer = outer_layer;
+        result
+    }
+
+    fn draw_in_bounds_in_layer(&mut self, canvas: &mut Canvas, element: &Element, bounds: Bounds) -> Result<(), miette::Error> {
+        match element {
+            Element::Rectangle(_) | Element::Image(_) | Element::Text(_) | Element::Qrcode(_) | Element::Barcode(_) | Element::Table(_) if !self.layer_is_drawn() => Ok(()),
+            Element::Rectangle(rect) => self.draw_rect(canvas, &rect.style, bounds),
+            Element::Image(image_frame) => self.draw_image(canvas, image_frame, bounds),
+            Element::Text(text) => self.draw_text(canvas, text, bounds),
+            Element::Qrcode(qr) => self.draw_qrcode(canvas, qr, bounds),
+            Element::Barcode(barcode) => self.draw_barcode(canvas, barcode, bounds),
+            Element::Table(table) => self.draw_table(canvas, table, bounds),
+            Element::Box(bx) => self.draw_box(canvas, bx, bounds),
+            Element::Row(stack) => self.draw_stack(canvas, stack, Axis::Horizontal, bounds),
+            Element::Column(stack) => self.draw_stack(canvas, stack, Axis::Vertical, bounds),
+            Element::Grid(grid) => self.draw_grid(canvas, grid, bounds),
+            Element::Use(usage) => self.draw_component(canvas, usage, bounds),
+            Element::Background(_) | Element::When(_) | Element::Switch(_) | Element::Repeat(_) => Ok(()),
+        }
+    }
     
-    fn draw_rect(&self, canvas: &mut Canvas, rect: &Rectangle) -> Result<(), miette::Error> {
-        let (fill, stroke) = self.compute_path_styles(&rect.style)?;
-        let irect = IRect::from_xywh(rect.x as i32, rect.y as i32, rect.w as i32, rect.h as i32);
+    fn template_context(&self) -> Result<TemplateContext<'_>, miette::Error> {
+        Ok(self.project.template_context_for(self.card)?.with_scopes(&self.scopes))
+    }
+
+    fn draw_rect(&self, canvas: &mut Canvas, style: &Vec<PathStyle>, bounds: Bounds) -> Result<(), miette::Error> {
+        let (fill, stroke) = self.compute_path_styles(style)?;
+        let rect = to_rect(&bounds);
     
         if let Some(fill) = fill {
-            canvas.draw_irect(irect, &fill);
+            canvas.draw_rect(rect, &fill);
         }
     
         if let Some(stroke) = stroke {
-            canvas.draw_irect(irect, &stroke);
+            canvas.draw_rect(rect, &stroke);
         }
     
         Ok(())
     }
 
-    fn draw_image(&mut self, canvas: &mut Canvas, image_frame: &Image) -> Result<(), miette::Error> {
+    fn draw_image(&mut self, canvas: &mut Canvas, image_frame: &Image, bounds: Bounds) -> Result<(), miette::Error> {
         let mut should_render = true;
-        let card_ctx = TryInto::<&handlebars::Context>::try_into(self.card)?;
+        let card_ctx = self.template_context()?;
         for image_style in &image_frame.styles {
             match image_style {
                 ImageStyle::OnlyIf(condition) => {
-                    should_render = should_render && condition.evaluate(card_ctx)?;
+                    should_render = should_render && condition.evaluate(&card_ctx)?;
                 }
             }
         }
@@ -59,20 +256,20 @@ impl<'a> CardRenderContext<'a> {
             return Ok(());
         }
 
-        let image_name = image_frame.name.render(card_ctx)?;
+        let image_name = image_frame.name.render(&card_ctx)?;
         let image = self.renderer.load_image(&image_name, self.project)?;
 
         let image = match image {
             Some(image) => image,
             None => {
-                self.draw_image_placeholder(canvas, &image_frame.frame, &image_name);
+                self.draw_image_placeholder(canvas, &bounds, &image_name);
                 return Ok(())
             }
         };
 
-        let horizontal_scale_factor = (image_frame.frame.w as f32)/(image.width() as f32);
-        let vertical_scale_factor = (image_frame.frame.h as f32)/(image.height() as f32);
-        let (frame_center_x, frame_center_y) = image_frame.frame.center();
+        let horizontal_scale_factor = bounds.w/(image.width() as f32);
+        let vertical_scale_factor = bounds.h/(image.height() as f32);
+        let (frame_center_x, frame_center_y) = bounds.center();
 
         let mut paint = Paint::new(Color4f::from(SkiaColor::BLACK), None);
         paint.set_anti_alias(true);
@@ -83,8 +280,8 @@ impl<'a> CardRenderContext<'a> {
                 let scaled_width = (image.width() as f32) * actual_scale_factor;
                 let scaled_height = (image.height() as f32) * actual_scale_factor;
                 let scaled_image_bounds = Rect::from_xywh(
-                    (frame_center_x as f32) - (scaled_width / 2.),
-                    (frame_center_y as f32) - (scaled_height / 2.),
+                    frame_center_x - (scaled_width / 2.),
+                    frame_center_y - (scaled_height / 2.),
                     scaled_width,
                     scaled_height,
                 );
@@ -96,51 +293,30 @@ impl<'a> CardRenderContext<'a> {
                 let scaled_width = (image.width() as f32) * actual_scale_factor;
                 let scaled_height = (image.height() as f32) * actual_scale_factor;
                 let scaled_image_bounds = Rect::from_xywh(
-                    (frame_center_x as f32) - (scaled_width / 2.),
-                    (frame_center_y as f32) - (scaled_height / 2.),
+                    frame_center_x - (scaled_width / 2.),
+                    frame_center_y - (scaled_height / 2.),
                     scaled_width,
                     scaled_height,
                 );
 
                 canvas.save();
-                canvas.clip_irect(
-                    IRect::from_xywh(
-                        image_frame.frame.x as i32,
-                        image_frame.frame.y as i32,
-                        image_frame.frame.w as i32,
-                        image_frame.frame.h as i32,
-                    ),
-                    ClipOp::Intersect,
-                );
+                canvas.clip_rect(to_rect(&bounds), ClipOp::Intersect, Some(true));
                 canvas.draw_image_rect(image, None, &scaled_image_bounds, &paint);
                 canvas.restore();
             },
             Scale::Stretch => {
-                canvas.draw_image_rect(image, None, Rect::from_xywh(
-                    image_frame.frame.x as f32,
-                    image_frame.frame.y as f32,
-                    image_frame.frame.w as f32,
-                    image_frame.frame.h as f32,
-                ), &paint);
+                canvas.draw_image_rect(image, None, to_rect(&bounds), &paint);
             },
             Scale::None => {
                 let unscaled_image_bounds = Rect::from_xywh(
-                    (frame_center_x as f32) - ((image.width() as f32) / 2.),
-                    (frame_center_y as f32) - ((image.height() as f32) / 2.),
+                    frame_center_x - ((image.width() as f32) / 2.),
+                    frame_center_y - ((image.height() as f32) / 2.),
                     image.width() as f32,
                     image.height() as f32,
                 );
 
                 canvas.save();
-                canvas.clip_irect(
-                    IRect::from_xywh(
-                        image_frame.frame.x as i32,
-                        image_frame.frame.y as i32,
-                        image_frame.frame.w as i32,
-                        image_frame.frame.h as i32,
-                    ),
-                    ClipOp::Intersect,
-                );
+                canvas.clip_rect(to_rect(&bounds), ClipOp::Intersect, Some(true));
                 canvas.draw_image_rect(image, None, &unscaled_image_bounds, &paint);
                 canvas.restore();
             },
@@ -148,7 +324,7 @@ impl<'a> CardRenderContext<'a> {
         Ok(())
     }
     
-    fn draw_image_placeholder(&self, canvas: &mut Canvas, frame: &Frame, image_name: &str) -> () {
+    fn draw_image_placeholder(&self, canvas: &mut Canvas, frame: &Bounds, image_name: &str) -> () {
         let mut foreground_paint = Paint::new(Color4f::from(SkiaColor::BLUE), None);
         foreground_paint.set_style(PaintStyle::Stroke);
         foreground_paint.set_stroke(true);
@@ -158,23 +334,18 @@ impl<'a> CardRenderContext<'a> {
         background_paint.set_style(PaintStyle::Fill);
         background_paint.set_stroke(false);
         background_paint.set_anti_alias(true);
-        let frame_irect = IRect::from_xywh(
-            frame.x as i32,
-            frame.y as i32,
-            frame.w as i32,
-            frame.h as i32,
-        );
+        let frame_rect = to_rect(frame);
 
-        canvas.draw_irect(frame_irect, &background_paint);
-        canvas.draw_irect(frame_irect, &foreground_paint);
+        canvas.draw_rect(frame_rect, &background_paint);
+        canvas.draw_rect(frame_rect, &foreground_paint);
         canvas.draw_line(
-            (frame.x as i32, frame.y as i32),
-            ((frame.x + frame.w) as i32, (frame.y + frame.h) as i32),
+            (frame.x, frame.y),
+            (frame.x + frame.w, frame.y + frame.h),
             &foreground_paint,
         );
         canvas.draw_line(
-            ((frame.x + frame.w) as i32, frame.y as i32),
-            (frame.x as i32, (frame.y + frame.h) as i32),
+            (frame.x + frame.w, frame.y),
+            (frame.x, frame.y + frame.h),
             &foreground_paint,
         );
 
@@ -193,15 +364,155 @@ impl<'a> CardRenderContext<'a> {
         let mut paragraph_builder = ParagraphBuilder::new(&paragraph_style, font_collection);
         paragraph_builder.add_text(image_name);
         let mut paragraph = paragraph_builder.build();
-        paragraph.layout(frame.w as f32);
+        paragraph.layout(frame.w);
 
         canvas.save();
         canvas.clip_irect(frame_irect, ClipOp::Intersect);
-        paragraph.paint(canvas, (frame.x as f32, frame.y as f32));
+        paragraph.paint(canvas, (frame.x, frame.y));
         canvas.restore();
     }
     
-    fn draw_text(&self, canvas: &mut Canvas, text: &Text) -> Result<(), miette::Error> {
+    fn draw_qrcode(&self, canvas: &mut Canvas, qr: &QrCode, bounds: Bounds) -> Result<(), miette::Error> {
+        let ctx = self.template_context()?;
+        if !conditions_hold(qr.conditions.iter(), &ctx)? {
+            return Ok(());
+        }
+        let symbol = match qr.symbol(&ctx) {
+            Ok(Some(symbol)) => symbol,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                log::warn!("While rendering card {}: {} Skipping it.", self.card.id, err);
+                return Ok(());
+            },
+        };
+
+        // QR codes keep their modules square, so the code is as large as
+        // possible while fitting in the frame, and centered in it
+        let modules_across = (symbol.width + 2 * qr.quiet_zone) as f32;
+        let module_size = bounds.w.min(bounds.h) / modules_across;
+        let (frame_center_x, frame_center_y) = bounds.center();
+        let origin = (
+            frame_center_x - (symbol.width as f32) * module_size / 2.,
+            frame_center_y - (symbol.height as f32) * module_size / 2.,
+        );
+
+        self.draw_symbol(canvas, &symbol, origin, (module_size, module_size), bounds, qr.foreground.as_ref(), qr.background.as_ref())
+    }
+
+    fn draw_barcode(&self, canvas: &mut Canvas, barcode: &Barcode, bounds: Bounds) -> Result<(), miette::Error> {
+        let ctx = self.template_context()?;
+        if !conditions_hold(barcode.conditions.iter(), &ctx)? {
+            return Ok(());
+        }
+        let symbol = match barcode.symbol(&ctx) {
+            Ok(Some(symbol)) => symbol,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                log::warn!("While rendering card {}: {} Skipping it.", self.card.id, err);
+                return Ok(());
+            },
+        };
+
+        // Bars are stretched to fill the frame, less the quiet zone on
+        // either side
+        let module_width = bounds.w / ((symbol.width + 2 * barcode.quiet_zone) as f32);
+        let origin = (
+            bounds.x + (barcode.quiet_zone as f32) * module_width,
+            bounds.y,
+        );
+
+        self.draw_symbol(canvas, &symbol, origin, (module_width, bounds.h), bounds, barcode.foreground.as_ref(), barcode.background.as_ref())
+    }
+
+    // Fill the frame with the background (if any), then draw all of the
+    // symbol's dark modules as a single path, with its top left module at
+    // `origin`
+    fn draw_symbol(&self, canvas: &mut Canvas, symbol: &Symbol, origin: (f32, f32), module_size: (f32, f32), bounds: Bounds, foreground: Option<&ColorRef>, background: Option<&ColorRef>) -> Result<(), miette::Error> {
+        if let Some(background) = background {
+            let mut background_paint = Paint::new(Color4f::from(self.resolve_color_ref(background)?), None);
+            background_paint.set_style(PaintStyle::Fill);
+            canvas.draw_rect(to_rect(&bounds), &background_paint);
+        }
+
+        let foreground = match foreground {
+            Some(foreground) => self.resolve_color_ref(foreground)?,
+            None => SkiaColor::BLACK,
+        };
+        let mut foreground_paint = Paint::new(Color4f::from(foreground), None);
+        foreground_paint.set_style(PaintStyle::Fill);
+        foreground_paint.set_anti_alias(true);
+
+        let (module_width, module_height) = module_size;
+        let mut path = Path::new();
+        for (col, row, length) in symbol.dark_runs() {
+            path.add_rect(Rect::from_xywh(
+                origin.0 + (col as f32) * module_width,
+                origin.1 + (row as f32) * module_height,
+                (length as f32) * module_width,
+                module_height,
+            ), None);
+        }
+        canvas.draw_path(&path, &foreground_paint);
+
+        Ok(())
+    }
+
+    // Tables are drawn with the same pieces as everything else: each stripe is
+    // a filled rectangle, each cell a text element, and each border a stroked
+    // rectangle
+    fn draw_table(&self, canvas: &mut Canvas, table: &Table, bounds: Bounds) -> Result<(), miette::Error> {
+        let ctx = self.template_context()?;
+        if !conditions_hold(table.conditions.iter(), &ctx)? {
+            return Ok(());
+        }
+        let layout = table.layout(&ctx, &bounds, self.extent_at(bounds))?;
+        if layout.overflow > 0 {
+            log::warn!("While rendering card {}: {} table row(s) didn't fit in the table's frame and were not drawn.", self.card.id, layout.overflow);
+        }
+
+        // Fixed column widths can add up to more than the frame is wide
+        canvas.save();
+        canvas.clip_rect(to_rect(&bounds), ClipOp::Intersect, Some(true));
+        let result = self.draw_table_layout(canvas, table, &layout);
+        canvas.restore();
+
+        result
+    }
+
+    fn draw_table_layout(&self, canvas: &mut Canvas, table: &Table, layout: &TableLayout) -> Result<(), miette::Error> {
+        let border: Vec<PathStyle> = table.border.iter().cloned().map(PathStyle::Stroke).collect();
+        for row in &layout.rows {
+            if let Some(ref color) = row.fill {
+                self.draw_rect(canvas, &vec![PathStyle::Solid(Solid { color: color.clone() })], row.bounds)?;
+            }
+            for cell in &row.cells {
+                self.draw_text(canvas, &cell.text, cell.text_bounds)?;
+            }
+        }
+        if !border.is_empty() {
+            for cell in layout.rows.iter().flat_map(|row| row.cells.iter()) {
+                self.draw_rect(canvas, &border, cell.bounds)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_text(&self, canvas: &mut Canvas, text: &Text, bounds: Bounds) -> Result<(), miette::Error> {
+        if let Some(laid_out) = self.lay_out_text(text, bounds.w)? {
+            if laid_out.truncated {
+                let rendered = text.contents.render(&self.template_context()?)?;
+                log::warn!("While rendering card {}: {} exceeded its maximum number of lines and was truncated.", self.card.id, describe_text(text, &rendered));
+            }
+            self.paint_text_blocks(canvas, &laid_out.blocks, &bounds, laid_out.columns.as_ref());
+        }
+
+        Ok(())
+    }
+
+    // Lays the text out to fit the given width. Returns `None` when the text
+    // is hidden by an only-if rule.
+    fn lay_out_text(&self, text: &Text, width: f32) -> Result<Option<LaidOutText>, miette::Error> {
         // TODO(#13): eventually support embedded markup to control styles
         // https://github.com/davidhollis/cardboard-rs/issues/13
         // TODO(#28): eventually support embedded icons
@@ -222,41 +533,64 @@ impl<'a> CardRenderContext<'a> {
         // 3. Then apply the inline styles
         text_styles.apply(text.inline_styles.as_slice());
         style_stack.push(("", text_styles.clone()));
+        let columns = text_styles.columns.filter(|cols| cols.count > 1).cloned();
+        let (max_lines, ellipsis) = (text_styles.max_lines, text_styles.ellipsis);
     
         if let Some(paragraph_style) = self.skia_text_styles(text_styles)? {
             let mut font_collection = FontCollection::new();
             font_collection.set_default_font_manager(FontMgr::new(), None);
-            let mut paragraph_builder = ParagraphBuilder::new(&paragraph_style, font_collection);
+            let card_ctx = self.template_context()?;
+            let layout_width = columns.as_ref().map_or(width, |cols| cols.column_width(width));
+            let mut runs: Vec<TextRun> = vec![];
     
             // Resolve the template and add the text to the builder
-            let filled_template = text.contents.render(self.card.try_into()?)?;
-            let formatted = format::parse(&filled_template);
-            for instruction in &formatted {
+            let filled_template = text.contents.render(&card_ctx)?;
+            let parsed = if self.project.strict_markup {
+                format::parse_strict(&filled_template, self.project.list_markup)
+                    .wrap_err_with(|| format!("While rendering card {}: invalid markup in text \"{}\"", self.card.id, text.contents.source()))?
+            } else {
+                format::parse(&filled_template, self.project.list_markup)
+            };
+            let formatted = format::glossary::expand(parsed, |keyword| {
+                let reminder = self.project.reminder_text_for(keyword);
+                if reminder.is_none() {
+                    log::warn!("While rendering card {}: no glossary entry found for keyword \"{}\"", self.card.id, keyword);
+                }
+                reminder
+            });
+            let formatted = format::keywords::apply(formatted, self.project.keyword_rules());
+            // Tags with arguments carry their own style definitions, which
+            // need to outlive the style stack that borrows from them.
+            let tag_styles: Vec<Vec<TextStyle>> = formatted.iter().map(|instruction| match instruction {
+                FormattedTextInstruction::PushStyleWithArguments(tag) => self.text_styles_for_tag(tag),
+                _ => vec![],
+            }).collect();
+
+            // List items are laid out as their own paragraphs so that wrapped
+            // lines can hang under the item's text rather than its marker.
+            let mut pending_blocks: Vec<(Vec<TextRun>, Option<(Paragraph, f32)>)> = vec![];
+            let mut current_marker: Option<(Paragraph, f32)> = None;
+            let mut block_has_text = false;
+            for (instruction, tag_style) in formatted.iter().zip(tag_styles.iter()) {
                 match instruction {
                     FormattedTextInstruction::AddText(ref text) => {
-                        paragraph_builder.add_text(text);
+                        let hyphenated = style_stack.last().and_then(|(_, current_style)| self.hyphenate(text, current_style));
+                        runs.push(TextRun::Text(hyphenated.unwrap_or_else(|| text.clone())));
+                        block_has_text = true;
                     },
                     FormattedTextInstruction::PushStyle(ref style_name) => {
                         if let Some(style_definition) = self.project.style_set_for(&style_name) {
-                            if let Some((_, ref previous_text_style)) = style_stack.last() {
-                                let mut new_text_style = previous_text_style.clone();
-                                new_text_style.apply(style_definition);
-                                if let Some(new_paragraph_style) = self.skia_text_styles(new_text_style.clone())? {
-                                    paragraph_builder.push_style(new_paragraph_style.text_style());
-                                    style_stack.push((style_name, new_text_style));
-                                } else {
-                                    log::warn!("While rendering card {}: style <{}> failed one or more only-if rules, ignoring tag.", self.card.id, style_name);
-                                }
-                            } else {
-                                log::warn!("While rendering card {}: no previous state found whn trying to apply style <{}>. Did you close too many tags?", self.card.id, style_name);
-                            }
+                            self.push_text_style(&mut runs, &mut style_stack, style_name, style_definition)?;
                         } else {
                             log::warn!("While rendering card {}: no style definition found for tag <{}>", self.card.id, style_name);
                         }
                     },
+                    FormattedTextInstruction::PushStyleWithArguments(ref tag) => {
+                        self.push_text_style(&mut runs, &mut style_stack, &tag.name, tag_style)?;
+                    },
                     FormattedTextInstruction::PopStyle(ref style_name) => {
                         if let Some((most_recent_style_name, _)) = style_stack.pop() {
-                            paragraph_builder.pop();
+                            runs.push(TextRun::Pop);
                             if most_recent_style_name != style_name {
                                 log::warn!("While rendering card {}: encountered </{}>, but the most recent tag was <{}>. Formatting may be incorrect", self.card.id, style_name, most_recent_style_name);
                             }
@@ -266,36 +600,447 @@ impl<'a> CardRenderContext<'a> {
                     },
                     FormattedTextInstruction::InsertPlaceholder(ref symbol_name) => {
                         log::warn!("While rendering card {}: encountered :{}:, but symbols are not supported yet. Ignoring.", self.card.id, symbol_name);
-                        paragraph_builder.add_text(format!(":{}:", symbol_name));
+                        runs.push(TextRun::Text(format!(":{}:", symbol_name)));
+                        block_has_text = true;
+                    },
+                    FormattedTextInstruction::StartListItem(_) | FormattedTextInstruction::EndListItem => {
+                        let finished_runs = std::mem::replace(&mut runs, self.start_text_block(&style_stack)?);
+                        if block_has_text || current_marker.is_some() {
+                            pending_blocks.push((finished_runs, current_marker.take()));
+                        }
+                        block_has_text = false;
+                        current_marker = match instruction {
+                            FormattedTextInstruction::StartListItem(ref marker) => match style_stack.last() {
+                                Some((_, current_style)) => self.list_marker(marker, current_style, &font_collection, layout_width)?,
+                                None => None,
+                            },
+                            _ => None,
+                        };
+                    },
+                }
+            }
+            if block_has_text || current_marker.is_some() || pending_blocks.is_empty() {
+                pending_blocks.push((runs, current_marker.take()));
+            }
+
+            // A line limit applies to the text as a whole, not to each
+            // paragraph, so each one only gets the lines the ones before it
+            // left over. Once they've all been used, the rest of the text is
+            // dropped.
+            let mut blocks: Vec<TextBlock> = vec![];
+            let mut remaining_lines = max_lines;
+            let mut truncated = false;
+            let block_count = pending_blocks.len();
+            for (idx, (mut block_runs, marker)) in pending_blocks.into_iter().enumerate() {
+                if remaining_lines == Some(0) {
+                    truncated = true;
+                    break;
+                }
+                let (marker, indent) = marker.map_or((None, 0f32), |(marker, indent)| (Some(marker), indent));
+                let mut block_style = paragraph_style.clone();
+                if let Some(lines) = remaining_lines {
+                    block_style.set_max_lines(lines);
+                }
+                let mut paragraph = self.build_paragraph(&block_style, &font_collection, &block_runs, layout_width - indent);
+                truncated = truncated || paragraph.did_exceed_max_lines();
+                if let Some(lines) = remaining_lines.as_mut() {
+                    *lines = lines.saturating_sub(paragraph.get_line_metrics().len());
+                    // Skia only ellipsizes a paragraph that overflows on its
+                    // own. When this one fills the last line exactly but more
+                    // text follows, add the ellipsis to the end of it, letting
+                    // Skia fit it onto the last line if it doesn't fit as is.
+                    if *lines == 0 && idx + 1 < block_count && !paragraph.did_exceed_max_lines() {
+                        if let Some(ellipsis) = ellipsis {
+                            block_runs.push(TextRun::Text(ellipsis.to_string()));
+                            paragraph = self.build_paragraph(&block_style, &font_collection, &block_runs, layout_width - indent);
+                        }
                     }
                 }
+                blocks.push(TextBlock { paragraph, marker, indent, direction: self.direction });
             }
     
-            // Lay out and draw the paragraph
-            let mut paragraph = paragraph_builder.build();
-            paragraph.layout(text.frame.w as f32);
-            paragraph.paint(canvas, (text.frame.x as f32, text.frame.y as f32));
+            Ok(Some(LaidOutText { blocks, columns, truncated }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn start_text_block(&self, style_stack: &[(&str, ComputedTextStyle<'_>)]) -> Result<Vec<TextRun>, miette::Error> {
+        let mut runs = vec![];
+
+        // Carry any styles that are still open over into the new paragraph.
+        // The bottom of the stack is the paragraph's own style.
+        for (_, open_style) in style_stack.iter().skip(1) {
+            if let Some(open_paragraph_style) = self.skia_text_styles(open_style.clone())? {
+                runs.push(TextRun::PushStyle(open_paragraph_style.text_style().clone()));
+            }
+        }
+
+        Ok(runs)
+    }
+
+    fn build_paragraph(&self, paragraph_style: &ParagraphStyle, font_collection: &FontCollection, runs: &[TextRun], width: f32) -> Paragraph {
+        let mut paragraph = lay_out_runs(paragraph_style, font_collection, runs, width, &[]);
+
+        // Skia will break lines at soft hyphens, but it won't draw a hyphen
+        // where it does. If any lines ended at one, lay the text out again
+        // with real hyphens in those spots. A hyphen can be wider than the
+        // soft hyphen it replaces and push a word onto the next line, so keep
+        // going until the hyphens drawn are the ones the lines end at.
+        let text: String = runs.iter().filter_map(|run| match run {
+            TextRun::Text(text) => Some(text.as_str()),
+            _ => None,
+        }).collect();
+        if !text.contains(format::hyphenate::SOFT_HYPHEN) {
+            return paragraph;
         }
+        let mut visible_hyphens = vec![];
+        for _ in 0..MAX_HYPHENATION_PASSES {
+            let broken_hyphens = soft_hyphens_at_line_ends(&paragraph, &text, &visible_hyphens);
+            if broken_hyphens == visible_hyphens {
+                break;
+            }
+            visible_hyphens = broken_hyphens;
+            paragraph = lay_out_runs(paragraph_style, font_collection, runs, width, &visible_hyphens);
+        }
+
+        paragraph
+    }
+
+    fn hyphenate(&self, text: &str, style: &ComputedTextStyle<'_>) -> Option<String> {
+        if !style.hyphenate {
+            return None;
+        }
+
+        let lang = style.hyphenation_lang.or(self.lang).unwrap_or(DEFAULT_HYPHENATION_LANGUAGE);
+        let hyphenated = format::hyphenate::hyphenate(text, lang);
+        if hyphenated.is_none() {
+            log::warn!("While rendering card {}: no hyphenation dictionary found for language \"{}\", so text will not be hyphenated.", self.card.id, lang);
+        }
+        hyphenated
+    }
+
+    fn list_marker(&self, marker: &ListMarker, style: &ComputedTextStyle<'_>, font_collection: &FontCollection, layout_width: f32) -> Result<Option<(Paragraph, f32)>, miette::Error> {
+        let label = match marker {
+            ListMarker::Bullet => style.list_bullet.unwrap_or(DEFAULT_LIST_BULLET),
+            ListMarker::Numbered(number) => number.as_str(),
+        };
+        let indent = match style.list_indent {
+            Some(indent) => indent as f32,
+            None => style.size.map_or(DEFAULT_FONT_SIZE, |size| size.pixel_size(self.dpi)) * 1.5,
+        };
+
+        if let Some(mut marker_style) = self.skia_text_styles(style.clone())? {
+            // Markers sit at the start of the line, which is on the right for
+            // right-to-left text
+            marker_style.set_text_align(skia_safe::textlayout::TextAlign::Start);
+            let mut marker_builder = ParagraphBuilder::new(&marker_style, font_collection.clone());
+            marker_builder.add_text(label);
+            let mut marker_paragraph = marker_builder.build();
+            marker_paragraph.layout(layout_width);
+            Ok(Some((marker_paragraph, indent.min(layout_width))))
+        } else {
+            Ok(None)
+        }
+    }
     
+    fn push_text_style<'s>(&self, runs: &mut Vec<TextRun>, style_stack: &mut Vec<(&'s str, ComputedTextStyle<'s>)>, style_name: &'s str, style_definition: &'s [TextStyle]) -> Result<(), miette::Error> {
+        if let Some((_, ref previous_text_style)) = style_stack.last() {
+            let mut new_text_style = previous_text_style.clone();
+            new_text_style.apply(style_definition);
+            if let Some(new_paragraph_style) = self.skia_text_styles(new_text_style.clone())? {
+                runs.push(TextRun::PushStyle(new_paragraph_style.text_style().clone()));
+                style_stack.push((style_name, new_text_style));
+            } else {
+                log::warn!("While rendering card {}: style <{}> failed one or more only-if rules, ignoring tag.", self.card.id, style_name);
+            }
+        } else {
+            log::warn!("While rendering card {}: no previous state found whn trying to apply style <{}>. Did you close too many tags?", self.card.id, style_name);
+        }
+
         Ok(())
     }
-    
-    fn draw_box(&mut self, canvas: &mut Canvas, bx: &Box) -> Result<(), miette::Error> {
+
+    fn text_styles_for_tag(&self, tag: &StyleTag) -> Vec<TextStyle> {
+        let mut styles = vec![];
+
+        if tag.takes_arguments() {
+            if !tag.arguments.is_empty() {
+                match inline::style_for_attribute(&tag.name, &tag.arguments.join(" ")) {
+                    Ok(style) => styles.push(style),
+                    Err(err) => log::warn!("While rendering card {}: ignoring arguments to tag <{}>: {}", self.card.id, tag.name, err),
+                }
+            }
+        } else if let Some(style_definition) = self.project.style_set_for(&tag.name) {
+            styles.extend_from_slice(style_definition);
+        } else {
+            log::warn!("While rendering card {}: no style definition found for tag <{}>", self.card.id, tag.name);
+        }
+
+        for (key, value) in &tag.attributes {
+            match inline::style_for_attribute(key, value) {
+                Ok(style) => styles.push(style),
+                Err(err) => log::warn!("While rendering card {}: ignoring attribute {}=\"{}\" on tag <{}>: {}", self.card.id, key, value, tag.name, err),
+            }
+        }
+
+        styles
+    }
+
+    fn paint_text_blocks(&self, canvas: &mut Canvas, blocks: &[TextBlock], frame: &Bounds, columns: Option<&Columns>) -> () {
+        let column_count = columns.map_or(1, |cols| cols.count);
+        let frame_height = frame.h;
+
+        // Blocks are stacked one after another. Walk their laid-out lines,
+        // starting a new column whenever the next line would overflow the
+        // bottom of the frame. Each column is then painted as a horizontal
+        // slice of the stacked blocks, clipped to the lines that belong to it.
+        // The last column takes every line that's left, overflowing the frame
+        // just like single-column text does.
+        let mut block_tops: Vec<f32> = vec![];
+        let mut slices: Vec<(f32, f32)> = vec![];
+        let mut slice_top: Option<f32> = None;
+        let mut slice_bottom = 0f32;
+        let mut block_top = 0f32;
+        for block in blocks {
+            block_tops.push(block_top);
+            for line in block.paragraph.get_line_metrics() {
+                let line_top = block_top + (line.baseline - line.ascent) as f32;
+                let line_bottom = block_top + (line.baseline + line.descent) as f32;
+                let top = *slice_top.get_or_insert(line_top);
+                if line_bottom - top > frame_height && slice_bottom > top && slices.len() + 1 < column_count {
+                    slices.push((top, slice_bottom));
+                    slice_top = Some(line_top);
+                }
+                slice_bottom = line_bottom;
+            }
+            block_top += block.paragraph.height();
+        }
+        if let Some(top) = slice_top {
+            slices.push((top, slice_bottom));
+        }
+
+        for (column, (top, bottom)) in slices.into_iter().enumerate() {
+            let column_x = frame.x + columns.map_or(0f32, |cols| cols.column_offset(frame.w, column));
+            canvas.save();
+            if let Some(columns) = columns {
+                canvas.clip_rect(
+                    Rect::from_xywh(column_x, frame.y, columns.column_width(frame.w), bottom - top),
+                    ClipOp::Intersect,
+                    Some(true),
+                );
+            }
+            for (block, block_top) in blocks.iter().zip(block_tops.iter()) {
+                if block_top + block.paragraph.height() <= top || *block_top >= bottom {
+                    continue;
+                }
+                let block_y = frame.y + block_top - top;
+                let paragraph_x = match block.direction {
+                    Direction::LeftToRight => column_x + block.indent,
+                    Direction::RightToLeft => column_x,
+                };
+                block.paragraph.paint(canvas, (paragraph_x, block_y));
+                if let Some(ref marker) = block.marker {
+                    // Line the marker up with the baseline of the item's first line
+                    let baseline_offset = match (block.paragraph.get_line_metrics_at(0), marker.get_line_metrics_at(0)) {
+                        (Some(item_line), Some(marker_line)) => (item_line.baseline - marker_line.baseline) as f32,
+                        _ => 0f32,
+                    };
+                    marker.paint(canvas, (column_x, block_y + baseline_offset));
+                }
+            }
+            canvas.restore();
+        }
+    }
+
+    fn draw_box(&mut self, canvas: &mut Canvas, bx: &Box, bounds: Bounds) -> Result<(), miette::Error> {
         canvas.save();
-        canvas.translate((bx.x as f32, bx.y as f32));
-        canvas.clip_rect(Rect::from_iwh(bx.w as i32, bx.h as i32), ClipOp::Intersect, Some(true));
+        canvas.translate((bounds.x, bounds.y));
+        canvas.clip_rect(Rect::from_wh(bounds.w, bounds.h), ClipOp::Intersect, Some(true));
+        let outer_extent = std::mem::replace(&mut self.extent, self.extent_at(bounds));
     
-        self.draw_elements(canvas, &bx.contents, bx.w, bx.h)?;
+        let result = self.draw_elements(canvas, &bx.contents, bounds.w, bounds.h);
     
+        self.extent = outer_extent;
         canvas.restore();
     
+        result
+    }
+    
+    fn draw_stack(&mut self, canvas: &mut Canvas, stack: &Stack, axis: Axis, bounds: Bounds) -> Result<(), miette::Error> {
+        let ctx = self.template_context()?;
+        let children = stack.children(&ctx)?;
+        let extent = self.extent_at(bounds);
+        let gap = stack.gap.resolve(&ctx, match axis {
+            Axis::Horizontal => extent.horizontal(),
+            Axis::Vertical => extent.vertical(),
+        })?;
+
+        // Measure everything that flows, skipping anything that's hidden
+        let sizes = children.iter()
+            .map(|child| self.measure_in_stack(child, stack, axis, &bounds, extent))
+            .collect::<Result<Vec<_>, _>>()?;
+        let flowing_sizes: Vec<(f32, f32)> = sizes.iter().flatten().copied().collect();
+        let mut slots = stack.arrange(axis, &bounds, gap, &flowing_sizes).into_iter();
+
+        // Then hand out the slots in flow order, and draw the children in z
+        // order, so that they overlap the same way they would outside of a
+        // stack. Backgrounds and repeats don't get a slot.
+        let mut placed: Vec<(&Element, Option<Bounds>)> = vec![];
+        for (child, size) in children.into_iter().zip(sizes) {
+            match (child, size) {
+                (Element::Background(_) | Element::Repeat(_), _) => placed.push((child, None)),
+                (_, Some(_)) => {
+                    if let Some(slot) = slots.next() {
+                        placed.push((child, Some(slot)));
+                    }
+                },
+                (_, None) => {},
+            }
+        }
+        placed.sort_by_key(|(child, _)| child.z());
+
+        for (child, slot) in placed {
+            match slot {
+                Some(slot) => self.draw_in_bounds(canvas, child, slot)?,
+                None => {
+                    canvas.save();
+                    canvas.translate((bounds.x, bounds.y));
+                    let outer_extent = std::mem::replace(&mut self.extent, extent);
+                    let result = self.draw_element(canvas, child, bounds.w, bounds.h);
+                    self.extent = outer_extent;
+                    canvas.restore();
+                    result?;
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_grid(&mut self, canvas: &mut Canvas, grid: &Grid, bounds: Bounds) -> Result<(), miette::Error> {
+        let ctx = self.template_context()?;
+        let layout = grid.layout(&ctx)?;
+        let extent = self.extent_at(bounds);
+        let gap = grid.gap.resolve(&ctx, extent.horizontal())?;
+        if layout.overflow > 0 {
+            log::warn!("While rendering card {}: {} element(s) didn't fit in a {}x{} grid and were not drawn.", self.card.id, layout.overflow, grid.rows, grid.cols);
+        }
+
+        for overlay in in_draw_order(layout.overlays) {
+            canvas.save();
+            canvas.translate((bounds.x, bounds.y));
+            let outer_extent = std::mem::replace(&mut self.extent, extent);
+            let result = self.draw_element(canvas, overlay, bounds.w, bounds.h);
+            self.extent = outer_extent;
+            canvas.restore();
+            result?;
+        }
+
+        for cell in layout.cells {
+            let cell_bounds = grid.cell_bounds(&bounds, gap, cell.row, cell.col);
+            canvas.save();
+            canvas.translate((cell_bounds.x, cell_bounds.y));
+            canvas.clip_rect(Rect::from_wh(cell_bounds.w, cell_bounds.h), ClipOp::Intersect, Some(true));
+            let outer_extent = std::mem::replace(&mut self.extent, self.extent_at(cell_bounds));
+            let result = in_draw_order(cell.contents).into_iter()
+                .try_for_each(|element| self.draw_element(canvas, element, cell_bounds.w, cell_bounds.h));
+            self.extent = outer_extent;
+            canvas.restore();
+            result?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_component(&mut self, canvas: &mut Canvas, usage: &Use, bounds: Bounds) -> Result<(), miette::Error> {
+        let component = match self.project.component_named(&usage.component) {
+            Some(component) => component,
+            None => return Ok(()),
+        };
+        if self.components_in_use.contains(&component.name.as_str()) {
+            return Err(miette!("While rendering card {}: component \"{}\" uses itself", self.card.id, component.name));
+        }
+        for argument in usage.unknown_arguments(component) {
+            log::warn!("While rendering card {}: component \"{}\" has no parameter \"{}\". Ignoring it.", self.card.id, component.name, argument);
+        }
+        let scope = usage.scope(component, &self.template_context()?)?;
+
+        self.components_in_use.push(&component.name);
+        self.scopes.push(scope);
+        canvas.save();
+        canvas.translate((bounds.x, bounds.y));
+        canvas.clip_rect(Rect::from_wh(bounds.w, bounds.h), ClipOp::Intersect, Some(true));
+        let outer_extent = std::mem::replace(&mut self.extent, self.extent_at(bounds));
+        let result = self.draw_elements(canvas, &component.contents, bounds.w, bounds.h);
+        self.extent = outer_extent;
+        canvas.restore();
+        self.scopes.pop();
+        self.components_in_use.pop();
+
+        result
+    }
+
+    // The (width, height) a child of a stack takes up, or `None` if it's
+    // hidden or doesn't flow
+    fn measure_in_stack(&self, element: &Element, stack: &Stack, axis: Axis, stack_bounds: &Bounds, stack_extent: Extent) -> Result<Option<(f32, f32)>, miette::Error> {
+        // Children of a stack are measured against the stack itself
+        let frame = match self.frame_of(element, stack_extent)? {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        let stretch = stack.align == StackAlignment::Stretch;
+        let ctx = self.template_context()?;
+
+        match element {
+            Element::Text(text) => {
+                let width = match axis {
+                    Axis::Vertical if stretch => stack_bounds.w,
+                    _ => frame.w,
+                };
+                Ok(self.lay_out_text(text, width)?.map(|laid_out| {
+                    // Text in columns already fills the height it was given
+                    let height = if laid_out.columns.is_some() { frame.h } else { laid_out.height().ceil() };
+                    (width, height)
+                }))
+            },
+            Element::Rectangle(rect) => Ok(conditions_hold(rect.style.iter().filter_map(|style| match style {
+                PathStyle::OnlyIf(condition) => Some(condition),
+                _ => None,
+            }), &ctx)?.then_some((frame.w, frame.h))),
+            Element::Image(image_frame) => Ok(conditions_hold(image_frame.styles.iter().map(|style| match style {
+                ImageStyle::OnlyIf(condition) => condition,
+            }), &ctx)?.then_some((frame.w, frame.h))),
+            Element::Qrcode(qr) => Ok(conditions_hold(qr.conditions.iter(), &ctx)?.then_some((frame.w, frame.h))),
+            Element::Barcode(barcode) => Ok(conditions_hold(barcode.conditions.iter(), &ctx)?.then_some((frame.w, frame.h))),
+            Element::Table(table) => Ok(conditions_hold(table.conditions.iter(), &ctx)?.then_some((frame.w, frame.h))),
+            _ => Ok(Some((frame.w, frame.h))),
+        }
+    }
+
+    fn draw_repeat(&mut self, canvas: &mut Canvas, repeat: &Repeat, frame_width: f32, frame_height: f32) -> Result<(), miette::Error> {
+        let extent = self.extent_at(Bounds { x: 0.0, y: 0.0, w: frame_width, h: frame_height });
+        let copies = repeat.copies(&self.template_context()?, extent)?;
+
+        for copy in copies {
+            self.scopes.push(copy.scope);
+            canvas.save();
+            canvas.translate(copy.offset);
+            let outer_extent = std::mem::replace(&mut self.extent, extent.shifted(copy.offset.0, copy.offset.1));
+            let result = self.draw_elements(canvas, &repeat.contents, frame_width, frame_height);
+            self.extent = outer_extent;
+            canvas.restore();
+            self.scopes.pop();
+            result?;
+        }
+
         Ok(())
     }
     
     fn resolve_color_ref(&self, color_ref: &ColorRef) -> Result<SkiaColor, miette::Error> {
         let color = match color_ref {
             ColorRef::Named(name_template) => {
-                let name = name_template.render(self.card.try_into()?)?;
+                let name = name_template.render(&self.template_context()?)?;
                 self.project.color_named(name.as_str())
             },
             ColorRef::Static(cb_color) => Ok(cb_color.clone()),
@@ -313,7 +1058,7 @@ impl<'a> CardRenderContext<'a> {
         let mut stroke_paint = Paint::new(Into::<Color4f>::into(SkiaColor::TRANSPARENT), None);
         let mut should_stroke = false;
         let mut should_render_at_all = true;
-        let card_ctx = TryInto::<&handlebars::Context>::try_into(self.card)?;
+        let card_ctx = self.template_context()?;
     
         for style in styles {
             match style {
@@ -343,7 +1088,7 @@ impl<'a> CardRenderContext<'a> {
                     fill_paint.set_color(self.resolve_color_ref(color)?);
                 },
                 PathStyle::OnlyIf(condition) => {
-                    should_render_at_all = should_render_at_all && condition.evaluate(card_ctx)?;
+                    should_render_at_all = should_render_at_all && condition.evaluate(&card_ctx)?;
                 }
             }
         }
@@ -360,10 +1105,10 @@ impl<'a> CardRenderContext<'a> {
     
     fn skia_text_styles(&self, styles: ComputedTextStyle<'_>) -> Result<Option<ParagraphStyle>, miette::Error> {
         let mut should_render = true;
-        let card_ctx = TryInto::<&handlebars::Context>::try_into(self.card)?;
+        let card_ctx = self.template_context()?;
         
         for cond in styles.conditions {
-            should_render = should_render && cond.evaluate(card_ctx)?;
+            should_render = should_render && cond.evaluate(&card_ctx)?;
         }
         
         if !should_render {
@@ -410,7 +1155,12 @@ impl<'a> CardRenderContext<'a> {
         if let Some(size) = styles.size {
             text_style.set_font_size(size.pixel_size(self.dpi));
         }
+        if let Some(lang) = self.lang {
+            text_style.set_locale(lang);
+        }
         let text_align = match styles.align {
+            Alignment::Start => skia_safe::textlayout::TextAlign::Start,
+            Alignment::End => skia_safe::textlayout::TextAlign::End,
             Alignment::Left => skia_safe::textlayout::TextAlign::Left,
             Alignment::Center => skia_safe::textlayout::TextAlign::Center,
             Alignment::Right => skia_safe::textlayout::TextAlign::Right,
@@ -438,6 +1188,71 @@ impl<'a> CardRenderContext<'a> {
         let mut paragraph_style = ParagraphStyle::new();
         paragraph_style.set_text_style(&text_style);
         paragraph_style.set_text_align(text_align);
+        paragraph_style.set_text_direction(match self.direction {
+            Direction::LeftToRight => TextDirection::LTR,
+            Direction::RightToLeft => TextDirection::RTL,
+        });
+        if let Some(max_lines) = styles.max_lines {
+            paragraph_style.set_max_lines(max_lines);
+        }
+        if let Some(ellipsis) = styles.ellipsis {
+            paragraph_style.set_ellipsis(ellipsis);
+        }
         Ok(Some(paragraph_style))
     }
 }
+
+fn lay_out_runs(paragraph_style: &ParagraphStyle, font_collection: &FontCollection, runs: &[TextRun], width: f32, visible_hyphens: &[usize]) -> Paragraph {
+    let mut paragraph_builder = ParagraphBuilder::new(paragraph_style, font_collection.clone());
+    let mut text_offset = 0usize;
+
+    for run in runs {
+        match run {
+            TextRun::Text(text) if visible_hyphens.is_empty() => {
+                paragraph_builder.add_text(text);
+            },
+            TextRun::Text(text) => {
+                let replaced: String = text.char_indices().map(|(idx, ch)| {
+                    if ch == format::hyphenate::SOFT_HYPHEN && visible_hyphens.contains(&(text_offset + idx)) { '-' } else { ch }
+                }).collect();
+                paragraph_builder.add_text(replaced);
+                text_offset += text.len();
+            },
+            TextRun::PushStyle(text_style) => {
+                paragraph_builder.push_style(text_style);
+            },
+            TextRun::Pop => {
+                paragraph_builder.pop();
+            },
+        }
+    }
+
+    let mut paragraph = paragraph_builder.build();
+    paragraph.layout(width);
+    paragraph
+}
+
+// Find the soft hyphens where the paragraph's lines were broken, as byte
+// offsets into `text`, the concatenated text of the runs. The paragraph was
+// laid out with the soft hyphens at `visible_hyphens` swapped for hyphens.
+fn soft_hyphens_at_line_ends(paragraph: &Paragraph, text: &str, visible_hyphens: &[usize]) -> Vec<usize> {
+    let lines = paragraph.get_line_metrics();
+    let hyphen_len = '-'.len_utf8();
+    let soft_hyphen_len = format::hyphenate::SOFT_HYPHEN.len_utf8();
+
+    // Line boundaries are UTF-8 byte offsets into the text Skia was given,
+    // which is shorter than `text` by a byte for each visible hyphen before
+    // them
+    text.match_indices(format::hyphenate::SOFT_HYPHEN)
+        .map(|(idx, _)| {
+            let shift = visible_hyphens.iter().filter(|visible| **visible < idx).count() * (soft_hyphen_len - hyphen_len);
+            let len = if visible_hyphens.contains(&idx) { hyphen_len } else { soft_hyphen_len };
+            (idx, idx - shift + len)
+        })
+        .filter(|(_, end)| {
+            lines.iter().take(lines.len().saturating_sub(1))
+                .any(|line| line.end_excluding_whitespaces == *end || line.end_index == *end)
+        })
+        .map(|(idx, _)| idx)
+        .collect()
+}
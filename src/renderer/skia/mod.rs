@@ -6,18 +6,23 @@ use thiserror::Error;
 
 use crate::{data::{project::Project}, config::sheets::{units, layout::Sheet}, layout::model::{geometry::Geometry, styles::text::ComputedTextStyle, BaseStyles}};
 
-use super::Renderer;
+use super::{LayerFilter, Renderer};
 
 mod drawing;
 mod pdf;
 
 pub struct SkiaRenderer {
     images: HashMap<String, Arc<Image>>,
+    layers: LayerFilter,
 }
 
 impl SkiaRenderer {
     pub fn new() -> SkiaRenderer {
-        SkiaRenderer { images: HashMap::new() }
+        SkiaRenderer { images: HashMap::new(), layers: LayerFilter::default() }
+    }
+
+    pub fn with_layers(layers: LayerFilter) -> SkiaRenderer {
+        SkiaRenderer { images: HashMap::new(), layers }
     }
 
     pub fn load_image(&mut self, image_name: &str, project: &Project) -> miette::Result<Option<Arc<Image>>> {
@@ -69,12 +74,12 @@ impl Renderer for SkiaRenderer {
         if let Some(BaseStyles { text: Some(ref style_definitions), .. }) = layout.base {
             base_text_styles.apply(style_definitions.styles.as_slice());
         }
-        let mut render_ctx = drawing::CardRenderContext::new(card, project, layout.geometry.dpi, self, base_text_styles);
+        let mut render_ctx = drawing::CardRenderContext::new(card, project, &layout.geometry, self, base_text_styles);
         render_ctx.draw_elements(
             &mut canvas,
             &layout.elements,
-            layout.geometry.width,
-            layout.geometry.height,
+            layout.geometry.width as f32,
+            layout.geometry.height as f32,
         )?;
 
         // Finalize and return the picture